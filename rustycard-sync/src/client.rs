@@ -0,0 +1,275 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{DEFAULT_PORT, PROTOCOL_HEADER, PROTOCOL_VERSIONS};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum SyncClientError {
+    Io(io::Error),
+    InvalidUrl(String),
+    Unauthorized,
+    // The versions of the protocol the server speaks, as it listed them.
+    UnsupportedProtocol(String),
+    Server(u16, String),
+    InvalidResponse,
+}
+
+impl fmt::Display for SyncClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncClientError::Io(err) => write!(f, "Could not reach the sync server: {err}"),
+            SyncClientError::InvalidUrl(url) => write!(f, "{url} is not a valid sync server address"),
+            SyncClientError::Unauthorized => {
+                write!(f, "The sync server did not accept the user name or password.")
+            }
+            SyncClientError::UnsupportedProtocol(versions) => write!(
+                f,
+                "The sync server only speaks protocol versions {versions}. Please update the app or the server."
+            ),
+            SyncClientError::Server(status, message) => {
+                write!(f, "The sync server failed with {status}: {message}")
+            }
+            SyncClientError::InvalidResponse => write!(f, "The sync server sent an invalid response."),
+        }
+    }
+}
+
+impl From<io::Error> for SyncClientError {
+    fn from(err: io::Error) -> Self {
+        SyncClientError::Io(err)
+    }
+}
+
+// Changes a copy of the collection pushed to the server, and their number
+// there.
+#[derive(Debug)]
+pub struct PushedChanges {
+    pub seq: u64,
+    pub bytes: Vec<u8>,
+}
+
+// Talks to a `rustycard-sync` server over plain HTTP.
+pub struct SyncClient {
+    host: String,
+    port: u16,
+    user: String,
+    password: String,
+}
+
+impl SyncClient {
+    // `url` looks like `http://host:port`, where the scheme and the port can
+    // be left out.
+    pub fn new(url: &str, user: &str, password: &str) -> Result<Self, SyncClientError> {
+        let invalid = || SyncClientError::InvalidUrl(url.to_owned());
+
+        let address = url.trim().trim_end_matches('/');
+        let address = address.strip_prefix("http://").unwrap_or(address);
+        if address.is_empty() || address.contains(['/', '?', '#', '@']) {
+            return Err(invalid());
+        }
+
+        // IPv6 addresses are written in brackets.
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| invalid())?)
+            }
+            _ => (address, DEFAULT_PORT),
+        };
+
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+            user: user.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+
+    // The changes pushed after the one numbered `after`, oldest first, as
+    // many as the server sends at once. None are left once this is empty.
+    pub fn pull(&self, after: u64) -> Result<Vec<PushedChanges>, SyncClientError> {
+        let body = self.request("GET", &format!("/changes?after={after}"), &[])?;
+
+        let mut changes = Vec::new();
+        let mut rest = body.as_slice();
+        while !rest.is_empty() {
+            let (seq, len) = match (read_u64(&mut rest), read_u64(&mut rest)) {
+                (Some(seq), Some(len)) if len as usize <= rest.len() => (seq, len as usize),
+                _ => return Err(SyncClientError::InvalidResponse),
+            };
+            let (bytes, next) = rest.split_at(len);
+            changes.push(PushedChanges {
+                seq,
+                bytes: bytes.to_vec(),
+            });
+            rest = next;
+        }
+        Ok(changes)
+    }
+
+    // Returns the number the server gave the changes.
+    pub fn push(&self, bytes: &[u8]) -> Result<u64, SyncClientError> {
+        let body = self.request("POST", "/changes", bytes)?;
+        String::from_utf8_lossy(&body)
+            .trim()
+            .parse()
+            .map_err(|_| SyncClientError::InvalidResponse)
+    }
+
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<Vec<u8>, SyncClientError> {
+        let address = (
+            self.host.trim_start_matches('[').trim_end_matches(']'),
+            self.port,
+        )
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| SyncClientError::InvalidUrl(self.host.clone()))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let versions = PROTOCOL_VERSIONS.map(|v| v.to_string()).join(", ");
+        let credentials = encode_base64(format!("{}:{}", self.user, self.password).as_bytes());
+        let mut head = format!(
+            "{method} {path} HTTP/1.1\r\n\
+            Host: {}:{}\r\n\
+            {PROTOCOL_HEADER}: {versions}\r\n\
+            Authorization: Basic {credentials}\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n",
+            self.host,
+            self.port,
+            body.len()
+        );
+        // The server accepts or turns down the request before the body is
+        // sent.
+        if !body.is_empty() {
+            head.push_str("Expect: 100-continue\r\n");
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut response = Response::read_head(&mut reader)?;
+        if response.status == 100 {
+            (&stream).write_all(body)?;
+            (&stream).flush()?;
+            response = Response::read_head(&mut reader)?;
+        }
+        // The server closes the connection after the response.
+        reader.read_to_end(&mut response.body)?;
+
+        match response.status {
+            200 => {}
+            401 => return Err(SyncClientError::Unauthorized),
+            400 if response.header(PROTOCOL_HEADER).is_some() && response.version().is_none() => {
+                let versions = response.header(PROTOCOL_HEADER).unwrap_or_default();
+                return Err(SyncClientError::UnsupportedProtocol(versions.to_owned()));
+            }
+            status => {
+                let message = String::from_utf8_lossy(&response.body).trim().to_owned();
+                return Err(SyncClientError::Server(status, message));
+            }
+        }
+        match response.version() {
+            Some(_) => Ok(response.body),
+            None => Err(SyncClientError::InvalidResponse),
+        }
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    // Reads the status line and the headers, leaving the body on `reader`.
+    fn read_head<R: BufRead>(reader: &mut R) -> Result<Self, SyncClientError> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if !line.ends_with('\n') {
+                return Err(SyncClientError::InvalidResponse);
+            }
+            match line.trim_end() {
+                "" => break,
+                line => lines.push(line.to_owned()),
+            }
+        }
+
+        let invalid = || SyncClientError::InvalidResponse;
+        let mut lines = lines.into_iter();
+        let status_line = lines.next().ok_or_else(invalid)?;
+        let status = status_line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(invalid)?;
+        let headers = lines
+            .map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_owned(), value.trim().to_owned()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            status,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The protocol version the server chose for the request, if it is one the
+    // app speaks.
+    fn version(&self) -> Option<u32> {
+        self.header(PROTOCOL_HEADER)?
+            .parse()
+            .ok()
+            .filter(|version| PROTOCOL_VERSIONS.contains(version))
+    }
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Option<u64> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(8);
+    *bytes = rest;
+    Some(u64::from_be_bytes(value.try_into().ok()?))
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let buffer = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, byte)| acc | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(ALPHABET[(buffer >> (18 - 6 * i) & 63) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,234 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, Read, Write},
+    path::PathBuf,
+};
+
+// Large enough for a collection with its assets pushed in one go.
+pub const MAX_BODY_SIZE: u64 = 512 * 1024 * 1024;
+
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+// The head of a request. Every connection carries one request, and its body
+// is left on the connection, so that it is only read once the request is
+// accepted.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    pub content_length: u64,
+}
+
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+enum Body {
+    Bytes(Vec<u8>),
+    // Pushed changes with their files and sizes, read as they are sent.
+    Changes(Vec<(u64, PathBuf, u64)>),
+}
+
+impl Request {
+    // Fails with `InvalidData` when the request isn't valid HTTP.
+    pub fn read_head<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut head_size = 0;
+        let mut read_line = |reader: &mut R| -> io::Result<String> {
+            let mut line = String::new();
+            head_size += reader
+                .by_ref()
+                .take((MAX_HEAD_SIZE - head_size) as u64)
+                .read_line(&mut line)?;
+            match line.ends_with('\n') {
+                true => Ok(line.trim_end().to_owned()),
+                false => Err(invalid("The request head is incomplete or too large")),
+            }
+        };
+
+        let request_line = read_line(reader)?;
+        let mut parts = request_line.split(' ');
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method.to_owned(), target)
+            }
+            _ => return Err(invalid("Invalid request line")),
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let (path, query) = (path.to_owned(), query.to_owned());
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            match line.split_once(':') {
+                Some((name, value)) => {
+                    headers.push((name.trim().to_owned(), value.trim().to_owned()))
+                }
+                None => return Err(invalid("Invalid header")),
+            }
+        }
+
+        let mut request = Request {
+            method,
+            path,
+            query,
+            headers,
+            content_length: 0,
+        };
+        request.content_length = match request.header("Content-Length").map(str::parse) {
+            Some(Ok(length)) => length,
+            Some(Err(_)) => return Err(invalid("Invalid Content-Length")),
+            None => 0,
+        };
+        Ok(request)
+    }
+
+    // Whether the client waits for a go-ahead before it sends the body.
+    pub fn expects_continue(&self) -> bool {
+        self.header("Expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    // The user name and password of HTTP basic authentication.
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let credentials = self.header("Authorization")?.strip_prefix("Basic ")?;
+        let credentials = String::from_utf8(decode_base64(credentials.trim())?).ok()?;
+        let (name, password) = credentials.split_once(':')?;
+        Some((name.to_owned(), password.to_owned()))
+    }
+}
+
+impl Response {
+    pub fn new(status: u16, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Body::Bytes(body),
+        }
+    }
+
+    // Each pushed change goes out as its number and length, both big endian
+    // u64, followed by its bytes.
+    pub fn changes(changes: Vec<(u64, PathBuf, u64)>) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: Body::Changes(changes),
+        }
+    }
+
+    pub fn text(status: u16, text: &str) -> Self {
+        Self::new(status, text.as_bytes().to_vec())
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::text(401, "Wrong user name or password")
+            .with_header("WWW-Authenticate", r#"Basic realm="rustycard-sync""#)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn write_to<W: Write>(&self, mut stream: W) -> io::Result<()> {
+        let length = match &self.body {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::Changes(changes) => changes.iter().map(|(_, _, len)| 16 + len).sum(),
+        };
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Length: {length}\r\nConnection: close\r\n\r\n"
+        ));
+        stream.write_all(head.as_bytes())?;
+
+        match &self.body {
+            Body::Bytes(bytes) => stream.write_all(bytes)?,
+            Body::Changes(changes) => {
+                for (seq, path, len) in changes {
+                    stream.write_all(&seq.to_be_bytes())?;
+                    stream.write_all(&len.to_be_bytes())?;
+                    let copied = io::copy(&mut File::open(path)?.take(*len), &mut stream)?;
+                    if copied != *len {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                }
+            }
+        }
+        stream.flush()
+    }
+}
+
+// Tells a client that waits for it to send the body of its request.
+pub fn write_continue<W: Write>(mut stream: W) -> io::Result<()> {
+    stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &c in text {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(bytes)
+}
@@ -0,0 +1,23 @@
+// A server that holds rustycard collections for several users, and the client
+// the app talks to it with. A collection is kept as the changes its copies
+// pushed, in order, and each copy pulls the ones it hasn't seen yet and merges
+// them itself, so the server never needs to read them.
+//
+// It speaks plain HTTP. To reach it over the internet, put it behind a
+// reverse proxy that adds TLS.
+
+pub use client::{PushedChanges, SyncClient, SyncClientError};
+pub use server::serve;
+
+mod client;
+mod http;
+mod server;
+mod store;
+pub mod users;
+
+// Versions of the protocol, oldest first.
+pub const PROTOCOL_VERSIONS: [u32; 1] = [1];
+
+pub const PROTOCOL_HEADER: &str = "Rustycard-Protocol";
+
+pub const DEFAULT_PORT: u16 = 8517;
@@ -0,0 +1,79 @@
+// The command line of the sync server.
+
+use std::{
+    env,
+    io::{self, BufRead},
+    net::TcpListener,
+    path::PathBuf,
+    process,
+};
+
+use rustycard_sync::{serve, users};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8517";
+
+const DEFAULT_DATA_DIR: &str = "rustycard-sync-data";
+
+const USAGE: &str = r#"Usage:
+    rustycard-sync serve [--address HOST:PORT] [--data DIR]
+    rustycard-sync add-user NAME [--data DIR]
+    rustycard-sync remove-user NAME [--data DIR]
+
+add-user reads the password from the first line of the standard input and
+replaces the password of an existing user."#;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = args.next();
+    let mut name = None;
+    let mut address = DEFAULT_ADDRESS.to_owned();
+    let mut data_dir = PathBuf::from(DEFAULT_DATA_DIR);
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--address", Some(value)) => address = value,
+            ("--data", Some(value)) => data_dir = PathBuf::from(value),
+            (value, None) if name.is_none() && !value.starts_with("--") => {
+                name = Some(value.to_owned())
+            }
+            _ => exit_with_usage(),
+        }
+    }
+
+    let result = match (command.as_deref(), name) {
+        (Some("serve"), None) => TcpListener::bind(&address).and_then(|listener| {
+            println!("Listening on {}", listener.local_addr()?);
+            serve(listener, &data_dir)
+        }),
+        (Some("add-user"), Some(name)) => read_password()
+            .and_then(|password| users::add_user(&data_dir, &name, &password))
+            .map(|_| println!("Added {name}")),
+        (Some("remove-user"), Some(name)) => {
+            users::remove_user(&data_dir, &name).map(|_| println!("Removed {name}"))
+        }
+        _ => exit_with_usage(),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+fn read_password() -> io::Result<String> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+    match password.is_empty() {
+        true => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The password can't be empty",
+        )),
+        false => Ok(password.to_owned()),
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
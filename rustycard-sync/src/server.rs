@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    io::{self, BufReader, Read},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    http::{self, Request, Response, MAX_BODY_SIZE},
+    store::Store,
+    users::Users,
+    PROTOCOL_HEADER, PROTOCOL_VERSIONS,
+};
+
+// Connections beyond this many are turned away until others finish, so that
+// clients can't use up the server's threads.
+const MAX_CONNECTIONS: usize = 64;
+
+// Pulls get the changes in pages of about this size, so that neither side
+// needs all of them at once.
+const MAX_PAGE_SIZE: u64 = 32 * 1024 * 1024;
+
+// How long a connection waits for the other side before it gives up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Turning a connection away happens on the thread that accepts the others, so
+// it waits much less.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Handles the connections to `listener` until it fails, each on its own
+// thread.
+pub fn serve(listener: TcpListener, data_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(data_dir)?;
+    let server = Arc::new(Server {
+        users: Users::new(data_dir),
+        store: Store::new(data_dir.join("collections")),
+        connections: AtomicUsize::new(0),
+    });
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+            stream.set_write_timeout(Some(BUSY_TIMEOUT))?;
+            Response::text(503, "The server is busy, please try again later")
+                .write_to(&stream)
+                .ok();
+            continue;
+        }
+        let connection = Connection(Arc::clone(&server));
+        thread::spawn(move || {
+            if let Err(err) = connection.0.handle_connection(stream) {
+                eprintln!("Connection failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+struct Server {
+    users: Users,
+    store: Store,
+    connections: AtomicUsize,
+}
+
+// Counts as an open connection until dropped, even if handling it panics.
+struct Connection(Arc<Server>);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Server {
+    fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let response = match Request::read_head(&mut reader) {
+            Ok(request) => self.handle(&request, &mut reader),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Response::text(400, &err.to_string())
+            }
+            Err(err) => return Err(err),
+        };
+        response.write_to(&stream)
+    }
+
+    // The request is accepted from its head, and only then is its body read
+    // from `body`.
+    fn handle(&self, request: &Request, body: &mut BufReader<&TcpStream>) -> Response {
+        // The client lists the versions it speaks and gets the newest one both
+        // sides know, or the versions the server knows.
+        let offered = request.header(PROTOCOL_HEADER).unwrap_or_default();
+        let version = offered
+            .split(',')
+            .filter_map(|version| version.trim().parse().ok())
+            .filter(|version| PROTOCOL_VERSIONS.contains(version))
+            .max();
+        let version = match version {
+            Some(version) => version,
+            None => {
+                let supported = PROTOCOL_VERSIONS.map(|v| v.to_string()).join(", ");
+                return Response::text(400, "Unsupported protocol version")
+                    .with_header(PROTOCOL_HEADER, &supported);
+            }
+        };
+
+        let user = match request.basic_auth() {
+            Some((name, password)) => match self.users.check(&name, &password) {
+                Ok(true) => name,
+                Ok(false) => return Response::unauthorized(),
+                Err(err) => return internal_error(err),
+            },
+            None => return Response::unauthorized(),
+        };
+
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/changes") => {
+                let after = match request.query("after").map(str::parse) {
+                    Some(Ok(after)) => after,
+                    None => 0,
+                    Some(Err(_)) => return Response::text(400, "Invalid value for after"),
+                };
+                match self.store.read_after(&user, after, MAX_PAGE_SIZE) {
+                    Ok(changes) => Response::changes(changes),
+                    Err(err) => internal_error(err),
+                }
+            }
+            ("POST", "/changes") if request.content_length == 0 => {
+                Response::text(400, "No changes were sent")
+            }
+            ("POST", "/changes") if request.content_length > MAX_BODY_SIZE => {
+                Response::text(413, "The changes are too large")
+            }
+            ("POST", "/changes") => self.receive_push(&user, request, body),
+            (_, "/changes") => Response::text(405, "Method not allowed"),
+            _ => Response::text(404, "Not found"),
+        };
+        response.with_header(PROTOCOL_HEADER, &version.to_string())
+    }
+
+    fn receive_push(
+        &self,
+        user: &str,
+        request: &Request,
+        body: &mut BufReader<&TcpStream>,
+    ) -> Response {
+        let mut push = match self.store.new_push(user) {
+            Ok(push) => push,
+            Err(err) => return internal_error(err),
+        };
+        let length = request.content_length;
+        let received = match request.expects_continue() {
+            true => http::write_continue(*body.get_ref()),
+            false => Ok(()),
+        }
+        .and_then(|_| io::copy(&mut body.take(length), &mut push));
+        if !matches!(received, Ok(received) if received == length) {
+            return Response::text(400, "The changes did not arrive completely");
+        }
+
+        match self.store.append(user, push) {
+            Ok(seq) => Response::text(200, &seq.to_string()),
+            Err(err) => internal_error(err),
+        }
+    }
+}
+
+fn internal_error(err: io::Error) -> Response {
+    eprintln!("{err}");
+    Response::text(500, "Internal server error")
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tempfile::NamedTempFile;
+
+// The pushed changes of every user, one file per push, numbered from 1.
+pub struct Store {
+    dir: PathBuf,
+    // Pushes to a collection have to be numbered one after the other, while
+    // other users' pushes don't need to wait.
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl Store {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            locks: Mutex::default(),
+        }
+    }
+
+    // A file to write a push to before it is appended. Dropping it leaves
+    // nothing behind.
+    pub fn new_push(&self, user: &str) -> io::Result<NamedTempFile> {
+        let dir = self.dir.join(user);
+        fs::create_dir_all(&dir)?;
+        NamedTempFile::new_in(dir)
+    }
+
+    // Returns the number of the stored changes.
+    pub fn append(&self, user: &str, push: NamedTempFile) -> io::Result<u64> {
+        push.as_file().sync_all()?;
+
+        let lock = self.lock(user);
+        let _numbering = lock.lock().unwrap();
+        let seq = self.list(user)?.last().map_or(1, |last| last + 1);
+        push.persist(self.dir.join(user).join(file_name(seq)))
+            .map_err(|err| err.error)?;
+        Ok(seq)
+    }
+
+    // The files of the changes pushed after `after` and their sizes, oldest
+    // first, as many as fit in `max_size` but at least one. Pushes show up
+    // here only once they are complete, so reading doesn't need the lock.
+    pub fn read_after(
+        &self,
+        user: &str,
+        after: u64,
+        max_size: u64,
+    ) -> io::Result<Vec<(u64, PathBuf, u64)>> {
+        let mut page = Vec::new();
+        let mut size = 0;
+        for seq in self.list(user)?.into_iter().filter(|seq| *seq > after) {
+            let path = self.dir.join(user).join(file_name(seq));
+            let len = fs::metadata(&path)?.len();
+            if !page.is_empty() && size + len > max_size {
+                break;
+            }
+            size += len;
+            page.push((seq, path, len));
+        }
+        Ok(page)
+    }
+
+    fn lock(&self, user: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap();
+        Arc::clone(locks.entry(user.to_owned()).or_default())
+    }
+
+    fn list(&self, user: &str) -> io::Result<Vec<u64>> {
+        let entries = match fs::read_dir(self.dir.join(user)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut seqs = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(seq) = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(SUFFIX))
+                .and_then(|seq| seq.parse().ok())
+            {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort_unstable();
+        Ok(seqs)
+    }
+}
+
+// Padded so that the files list in order.
+fn file_name(seq: u64) -> String {
+    format!("{seq:020}{SUFFIX}")
+}
+
+const SUFFIX: &str = ".rustyzip";
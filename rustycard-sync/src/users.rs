@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use hmac::Hmac;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+// One user per line as `name:rounds:salt:hash`, where the hash is the
+// PBKDF2-HMAC-SHA256 of the password with the salt, both in hex. The file is
+// read on every request, so changes take effect without a restart.
+const USERS_FILE_NAME: &str = "users";
+
+const SALT_SIZE: usize = 16;
+
+const HASH_SIZE: usize = 32;
+
+// Kept with each user, so that it can be raised for new passwords without
+// breaking the old ones.
+const ROUNDS: u32 = 600_000;
+
+pub fn add_user(data_dir: &Path, name: &str, password: &str) -> io::Result<()> {
+    // Names are used as directory names.
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !is_valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "User names can only contain letters, digits, - and _",
+        ));
+    }
+
+    let mut salt = [0; SALT_SIZE];
+    getrandom::getrandom(&mut salt).map_err(io::Error::from)?;
+
+    let mut lines = read_users(data_dir)?;
+    lines.retain(|line| user_name(line) != Some(name));
+    lines.push(format!(
+        "{name}:{ROUNDS}:{}:{}",
+        to_hex(&salt),
+        to_hex(&hash(&salt, ROUNDS, password))
+    ));
+    write_users(data_dir, &lines)
+}
+
+pub fn remove_user(data_dir: &Path, name: &str) -> io::Result<()> {
+    let mut lines = read_users(data_dir)?;
+    let count = lines.len();
+    lines.retain(|line| user_name(line) != Some(name));
+    if lines.len() == count {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("There is no user {name}"),
+        ));
+    }
+    write_users(data_dir, &lines)
+}
+
+// Checks passwords against the users file. A right password is remembered
+// with the user's line, so that further requests of a client don't pay for
+// the slow hash until the user changes.
+pub(crate) struct Users {
+    data_dir: PathBuf,
+    checked: Mutex<HashMap<String, [u8; HASH_SIZE]>>,
+}
+
+impl Users {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_owned(),
+            checked: Mutex::default(),
+        }
+    }
+
+    pub fn check(&self, name: &str, password: &str) -> io::Result<bool> {
+        let users = read_users(&self.data_dir)?;
+        let line = match users.iter().find(|line| user_name(line) == Some(name)) {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+
+        let quick_hash: [u8; HASH_SIZE] = Sha256::new()
+            .chain_update(line)
+            .chain_update("\n")
+            .chain_update(password)
+            .finalize()
+            .into();
+        if let Some(checked) = self.checked.lock().unwrap().get(line) {
+            if constant_time_eq(checked, &quick_hash) {
+                return Ok(true);
+            }
+        }
+
+        let mut parts = line.split(':').skip(1);
+        let is_right = match (parts.next(), parts.next(), parts.next()) {
+            (Some(rounds), Some(salt), Some(expected)) => match (rounds.parse(), from_hex(salt)) {
+                (Ok(rounds), Some(salt)) => constant_time_eq(
+                    to_hex(&hash(&salt, rounds, password)).as_bytes(),
+                    expected.as_bytes(),
+                ),
+                _ => false,
+            },
+            _ => false,
+        };
+        if is_right {
+            self.checked
+                .lock()
+                .unwrap()
+                .insert(line.to_owned(), quick_hash);
+        }
+        Ok(is_right)
+    }
+}
+
+fn read_users(data_dir: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(data_dir.join(USERS_FILE_NAME)) {
+        Ok(data) => Ok(data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_users(data_dir: &Path, lines: &[String]) -> io::Result<()> {
+    fs::create_dir_all(data_dir)?;
+    let mut file = NamedTempFile::new_in(data_dir)?;
+    for line in lines {
+        writeln!(file, "{line}")?;
+    }
+    file.persist(data_dir.join(USERS_FILE_NAME))
+        .map_err(|err| err.error)?;
+    Ok(())
+}
+
+fn user_name(line: &str) -> Option<&str> {
+    line.split(':').next()
+}
+
+fn hash(salt: &[u8], rounds: u32, password: &str) -> [u8; HASH_SIZE] {
+    let mut hash = [0; HASH_SIZE];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, rounds, &mut hash);
+    hash
+}
+
+// Takes as long for a wrong password as for a right one.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
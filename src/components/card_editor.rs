@@ -5,7 +5,7 @@ use native_dialog::FileDialog;
 
 use crate::services::config::use_config;
 
-use super::MarkdownView;
+use super::{MarkdownView, TagInput};
 
 #[allow(non_snake_case)]
 pub fn CardEditor<'a>(cx: Scope<'a, CardEditorProps<'a>>) -> Element {
@@ -51,10 +51,12 @@ pub fn CardEditor<'a>(cx: Scope<'a, CardEditorProps<'a>>) -> Element {
         MarkdownView {
             text: "{content}",
         }
+        TagInput {
+            tags: cx.props.tags,
+        }
         button {
             onclick: |_| {
                 cx.props.onsave.call(content);
-                content.set(String::new());
             },
             "Save"
         }
@@ -65,5 +67,6 @@ pub fn CardEditor<'a>(cx: Scope<'a, CardEditorProps<'a>>) -> Element {
 pub struct CardEditorProps<'a> {
     #[props(default)]
     initial_value: String,
+    tags: &'a UseState<Vec<String>>,
     onsave: EventHandler<'a, &'a str>,
 }
@@ -0,0 +1,92 @@
+use dioxus::prelude::*;
+
+use crate::services::{
+    database::{use_database, Id},
+    errors::use_error_log,
+};
+
+use super::{CardEditor, NoteEditor};
+
+#[allow(non_snake_case)]
+pub fn CardForm<'a>(cx: Scope<'a, CardFormProps<'a>>) -> Element {
+    let id = cx.props.id;
+    assert!(id != 0);
+
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let card = use_state(&cx, || errors.ok(db.borrow().get_card(id)));
+    let tags = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_tags_for_card(id))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.name)
+            .collect::<Vec<_>>()
+    });
+
+    let card = match card.get() {
+        Some(card) => card,
+        None => return None,
+    };
+
+    let note = card
+        .note
+        .and_then(|note| errors.ok(db.borrow().get_note(note)));
+    let editor = match note {
+        Some(note) => {
+            let note_id = note.id;
+            cx.render(rsx! {
+                NoteEditor {
+                    note_type: note.note_type,
+                    initial_values: note.values,
+                    tags: tags,
+                    onsave: move |values: &[String]| {
+                        let saved = db.borrow_mut().transaction(|db| {
+                            db.update_note(note_id, values)?;
+                            db.set_note_tags(note_id, tags)
+                        });
+                        if errors.ok(saved).is_some() {
+                            cx.props.onsave.call(id);
+                        }
+                    },
+                }
+            })
+        }
+        None => cx.render(rsx! {
+            CardEditor {
+                initial_value: card.content.clone(),
+                tags: tags,
+                onsave: move |content: &str| {
+                    if !content.is_empty() {
+                        let saved = db.borrow_mut().transaction(|db| {
+                            db.update_card_content(id, content)?;
+                            db.set_card_tags(id, tags)
+                        });
+                        if errors.ok(saved).is_some() {
+                            cx.props.onsave.call(id);
+                        }
+                    }
+                },
+            }
+        }),
+    };
+
+    cx.render(rsx! {
+        editor
+        button {
+            onclick: move |_| {
+                if errors.ok(db.borrow_mut().delete_card(id)).is_some() {
+                    cx.props.ondelete.call(id);
+                }
+            },
+            "Move to trash"
+        }
+    })
+}
+
+#[derive(Props)]
+pub struct CardFormProps<'a> {
+    id: Id,
+    onsave: EventHandler<'a, Id>,
+    ondelete: EventHandler<'a, Id>,
+}
@@ -0,0 +1,109 @@
+use dioxus::prelude::*;
+
+use crate::services::{
+    database::{use_database, Id},
+    diff::{self, DiffLine},
+    errors::use_error_log,
+};
+
+#[allow(non_snake_case)]
+pub fn CardHistory<'a>(cx: Scope<'a, CardHistoryProps<'a>>) -> Element {
+    let id = cx.props.id;
+    assert!(id != 0);
+
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let revisions = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_card_revisions(id))
+            .unwrap_or_default()
+    });
+    let card = use_state(&cx, || errors.ok(db.borrow().get_card(id)));
+    let selected = use_state(&cx, || None::<usize>);
+
+    if revisions.is_empty() {
+        return cx.render(rsx! {
+            p { "No earlier versions" }
+        });
+    }
+
+    let (content, is_note_card) = match card.get() {
+        Some(card) => (card.content.as_str(), card.note.is_some()),
+        None => return None,
+    };
+
+    // Each revision is compared with the version that replaced it.
+    let diff = selected.map(|i| {
+        let newer = match i {
+            0 => content,
+            i => revisions[i - 1].content.as_str(),
+        };
+        diff::diff_lines(&revisions[i].content, newer)
+    });
+
+    cx.render(rsx! {
+        ul {
+            class: "card-history",
+            revisions.iter().enumerate().map(|(i, revision)| {
+                let created_at = revision.created_at.format("%Y-%m-%d %H:%M:%S");
+                let class = match *selected.get() == Some(i) {
+                    true => "selected",
+                    false => "",
+                };
+
+                rsx! {
+                    li {
+                        key: "{revision.id}",
+                        class: "{class}",
+                        onclick: move |_| {
+                            selected.set(Some(i));
+                        },
+                        "{created_at}"
+                    }
+                }
+            })
+        }
+        diff.map(|diff| rsx! {
+            pre {
+                class: "diff",
+                diff.iter().map(|line| match line {
+                    DiffLine::Unchanged(line) => rsx! { div { "  {line}" } },
+                    DiffLine::Added(line) => rsx! { div { class: "added", "+ {line}" } },
+                    DiffLine::Removed(line) => rsx! { div { class: "removed", "- {line}" } },
+                })
+            }
+            // Note cards are rendered from their note, so editing the note is
+            // the only way to change them.
+            (!is_note_card).then(|| rsx! {
+                button {
+                    onclick: move |_| {
+                        if let Some(i) = *selected.get() {
+                            let restored = db.borrow_mut().restore_card_revision(revisions[i].id);
+                            if errors.ok(restored).is_none() {
+                                return;
+                            }
+                            revisions.set(
+                                errors
+                                    .ok(db.borrow().get_card_revisions(id))
+                                    .unwrap_or_default(),
+                            );
+                            card.set(errors.ok(db.borrow().get_card(id)));
+                            selected.set(None);
+                            cx.props.onrestore.call(id);
+                        }
+                    },
+                    "Restore this version"
+                }
+            })
+            is_note_card.then(|| rsx! {
+                p { "Edit the note to change this card." }
+            })
+        })
+    })
+}
+
+#[derive(Props)]
+pub struct CardHistoryProps<'a> {
+    id: Id,
+    onrestore: EventHandler<'a, Id>,
+}
@@ -0,0 +1,25 @@
+use dioxus::prelude::*;
+
+use crate::services::errors::use_error_log;
+
+use super::Button;
+
+#[allow(non_snake_case)]
+pub fn ErrorBanner(cx: Scope) -> Element {
+    let errors = use_error_log(&cx);
+    cx.use_hook(|_| errors.subscribe(cx.schedule_update()));
+
+    cx.render(rsx! {
+        errors.errors().into_iter().enumerate().map(|(i, error)| rsx! {
+            div {
+                key: "{i}",
+                class: "error-banner",
+                span { "{error}" }
+                Button {
+                    onclick: move |_| errors.dismiss(i),
+                    name: "Dismiss",
+                }
+            }
+        })
+    })
+}
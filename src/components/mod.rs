@@ -1,7 +1,17 @@
 mod button;
 mod card_editor;
+mod card_form;
+mod card_history;
+mod error_banner;
 mod markdown_view;
+mod note_editor;
+mod tag_input;
 
 pub use button::*;
 pub use card_editor::*;
+pub use card_form::*;
+pub use card_history::*;
+pub use error_banner::*;
 pub use markdown_view::*;
+pub use note_editor::*;
+pub use tag_input::*;
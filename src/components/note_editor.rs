@@ -0,0 +1,83 @@
+use dioxus::prelude::*;
+
+use crate::services::{
+    database::{use_database, Id},
+    errors::use_error_log,
+    template,
+};
+
+use super::{MarkdownView, TagInput};
+
+#[allow(non_snake_case)]
+pub fn NoteEditor<'a>(cx: Scope<'a, NoteEditorProps<'a>>) -> Element {
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let fields = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_note_fields(cx.props.note_type))
+            .unwrap_or_default()
+    });
+    let templates = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_note_templates(cx.props.note_type))
+            .unwrap_or_default()
+    });
+    let values = use_state(&cx, || {
+        let mut values = cx.props.initial_values.to_owned();
+        values.resize(fields.len(), String::new());
+        values
+    });
+
+    let named_values = fields
+        .iter()
+        .zip(values.iter())
+        .map(|(field, value)| (field.name.as_str(), value.as_str()))
+        .collect::<Vec<_>>();
+
+    cx.render(rsx! {
+        fields.iter().enumerate().map(|(i, field)| rsx! {
+            div {
+                key: "{field.id}",
+                h3 { "{field.name}" }
+                textarea {
+                    rows: "4",
+                    cols: "80",
+                    value: format_args!("{}", values[i]),
+                    oninput: move |evt| {
+                        values.make_mut()[i] = evt.value.clone();
+                    },
+                }
+            }
+        })
+        templates.iter().map(|t| {
+            let content = template::render(&t.content, &named_values);
+            rsx! {
+                div {
+                    key: "{t.id}",
+                    h3 { "{t.name}" }
+                    MarkdownView {
+                        text: "{content}",
+                    }
+                }
+            }
+        })
+        TagInput {
+            tags: cx.props.tags,
+        }
+        button {
+            onclick: |_| {
+                cx.props.onsave.call(values);
+            },
+            "Save"
+        }
+    })
+}
+
+#[derive(Props)]
+pub struct NoteEditorProps<'a> {
+    note_type: Id,
+    #[props(default)]
+    initial_values: Vec<String>,
+    tags: &'a UseState<Vec<String>>,
+    onsave: EventHandler<'a, &'a [String]>,
+}
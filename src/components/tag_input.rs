@@ -0,0 +1,78 @@
+use dioxus::prelude::*;
+
+use crate::services::{database::use_database, errors::use_error_log};
+
+#[allow(non_snake_case)]
+pub fn TagInput<'a>(cx: Scope<'a, TagInputProps<'a>>) -> Element {
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let all_tags = use_state(&cx, || {
+        errors.ok(db.borrow().get_tags()).unwrap_or_default()
+    });
+    let input = use_state(&cx, String::new);
+    let tags = cx.props.tags;
+
+    let query = input.trim().to_lowercase();
+    let suggestions = all_tags
+        .iter()
+        .filter(|t| !query.is_empty() && t.name.to_lowercase().contains(&query))
+        .filter(|t| !tags.contains(&t.name))
+        .take(8);
+
+    cx.render(rsx! {
+        div {
+            class: "tag-input",
+            tags.iter().map(|name| rsx! {
+                span {
+                    key: "{name}",
+                    class: "tag-chip",
+                    "{name}"
+                    button {
+                        onclick: move |_| {
+                            tags.make_mut().retain(|t| t != name);
+                        },
+                        "×"
+                    }
+                }
+            })
+            input {
+                placeholder: "Add tag",
+                value: "{input}",
+                oninput: |evt| {
+                    input.set(evt.value.clone());
+                },
+                onkeydown: |evt| {
+                    if evt.key == "Enter" {
+                        add_tag(tags, input);
+                        input.set(String::new());
+                    }
+                },
+            }
+            ul {
+                class: "tag-suggestions",
+                suggestions.map(|t| rsx! {
+                    li {
+                        key: "{t.id}",
+                        onclick: move |_| {
+                            add_tag(tags, &t.name);
+                            input.set(String::new());
+                        },
+                        "{t.name}"
+                    }
+                })
+            }
+        }
+    })
+}
+
+fn add_tag(tags: &UseState<Vec<String>>, name: &str) {
+    let name = name.trim();
+    if !name.is_empty() && !tags.iter().any(|t| t == name) {
+        tags.make_mut().push(name.to_owned());
+    }
+}
+
+#[derive(Props)]
+pub struct TagInputProps<'a> {
+    tags: &'a UseState<Vec<String>>,
+}
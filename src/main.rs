@@ -1,65 +1,159 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use dioxus::{desktop::use_window, prelude::*};
 
 use services::{
+    autosave::{save_all, save_on_close, use_autosave, CloseRequests},
     config::{use_config, Config},
-    database::{use_database, Database},
+    database::{sync_in_background, use_database, Database},
+    errors::{use_error_log, ErrorLog},
 };
 
-use crate::components::Button;
+use crate::components::{Button, ErrorBanner};
 
 mod components;
 mod pages;
 mod services;
 
+struct AppProps {
+    close_requests: Cell<Option<CloseRequests>>,
+}
+
 fn main() {
-    dioxus::desktop::launch_cfg(app, |c| {
+    let (save_on_close, close_requests) = save_on_close();
+    let props = AppProps {
+        close_requests: Cell::new(Some(close_requests)),
+    };
+    dioxus::desktop::launch_with_props(app, props, |c| {
         let head = format!(
             r#"
             <link rel="stylesheet" href="https://cdnjs.cloudflare.com/ajax/libs/normalize/8.0.1/normalize.min.css">
             <style>{}</style>
+            <style>{}</style>
+            <style>{}</style>
+            <style>{}</style>
+            <style>{}</style>
+            <style>{}</style>
+            <style>{}</style>
         "#,
-            include_str!("components/button.css")
+            include_str!("components/button.css"),
+            include_str!("components/card_history.css"),
+            include_str!("components/error_banner.css"),
+            include_str!("components/tag_input.css"),
+            include_str!("pages/add_card.css"),
+            include_str!("pages/cards.css"),
+            include_str!("pages/conflicts.css")
         );
-        c.with_custom_head(head)
+        // The event loop drops the config, and the handler with it, once the
+        // window has closed.
+        c.with_custom_head(head).with_event_handler(move |_, _| {
+            let _ = &save_on_close;
+        })
     });
 }
 
-fn app(cx: Scope) -> Element {
-    cx.use_hook(|_| {
-        let cfg = Config::new();
-        let db = Database::new(&cfg);
+fn app(cx: Scope<AppProps>) -> Element {
+    // Taken on the first render. Without a database it's dropped, so closing
+    // the window doesn't wait for a save.
+    let close_requests = cx.props.close_requests.take();
+    let error = cx.use_hook(|_| {
+        let errors = ErrorLog::default();
+        // Without a readable config the app starts with the defaults.
+        let cfg = errors.ok(Config::new()).unwrap_or_default();
+
+        let error = match Database::new(&cfg) {
+            Ok(db) => {
+                cx.provide_context(Rc::new(RefCell::new(db)));
+                None
+            }
+            Err(err) => Some(err.to_string()),
+        };
+        cx.provide_context(Rc::new(errors));
         cx.provide_context(Rc::new(RefCell::new(cfg)));
-        cx.provide_context(Rc::new(RefCell::new(db)));
+        error
     });
+    let window = use_window(&cx);
+
+    // The database is left untouched so that a newer app can still open it.
+    if let Some(error) = error {
+        return cx.render(rsx! {
+            h1 { "Could not open the database" }
+            p { "{error}" }
+            Button {
+                onclick: move |_| {
+                    window.close();
+                },
+                name: "Quit",
+            }
+        });
+    }
 
     let cfg = use_config(&cx);
     let db = use_database(&cx);
-    let window = use_window(&cx);
+    let errors = use_error_log(&cx);
+    use_autosave(&cx, close_requests);
+    use_future(&cx, (), |_| {
+        let cfg = cx.consume_context::<Rc<RefCell<Config>>>().unwrap();
+        let db = cx.consume_context::<Rc<RefCell<Database>>>().unwrap();
+        let errors = cx.consume_context::<Rc<ErrorLog>>().unwrap();
+        let update = cx.schedule_update();
+        // Runs once the window is up. A failed sync leaves both copies as they
+        // were.
+        async move {
+            errors.ok(sync_in_background(&cfg, &db).await);
+            update();
+        }
+    });
+    let saved_searches = errors
+        .ok(db.borrow().get_saved_searches())
+        .unwrap_or_default();
 
     cx.render(rsx! {
+        ErrorBanner {}
         Router {
             h1 { "Navigation" }
             ul {
                 Link { to: "/review", li { "Review"  }}
                 Link { to: "/cards", li { "Cards"  }}
                 Link { to: "/add_card", li { "Add card"  }}
-                Link { to: "/edit_card/1", li { "Edit card"  }}
+                Link { to: "/tags", li { "Tags"  }}
+                Link { to: "/trash", li { "Trash"  }}
+                Link { to: "/conflicts", li { "Conflicts"  }}
                 Link { to: "/settings", li { "Settings"  }}
             }
+            h2 { "Saved searches" }
+            ul {
+                saved_searches.iter().map(|saved| rsx! {
+                    li {
+                        key: "{saved.id}",
+                        "{saved.name} "
+                        Link { to: "/cards/saved/{saved.id}", "Browse" }
+                        " "
+                        Link { to: "/review/saved/{saved.id}", "Study" }
+                    }
+                })
+            }
             Button {
+                // The window stays open if saving fails, so nothing is lost.
                 onclick: move |_| {
-                    cfg.borrow().save();
-                    db.borrow().save(&*cfg.borrow());
-                    window.close();
+                    if save_all(cfg, db, errors) {
+                        window.close();
+                    }
                 },
                 name: "Quit",
             }
             Route { to: "/review", pages::Review {} }
+            Route { to: "/review/saved/:id", pages::Review {} }
             Route { to: "/cards", pages::Cards {} }
+            Route { to: "/cards/saved/:id", pages::Cards {} }
             Route { to: "/add_card", pages::AddCard {} }
             Route { to: "/edit_card/:id", pages::EditCard {} }
+            Route { to: "/tags", pages::Tags {} }
+            Route { to: "/trash", pages::Trash {} }
+            Route { to: "/conflicts", pages::Conflicts {} }
             Route { to: "/settings", pages::Settings {} }
             Redirect { from: "", to: "/review" }
         }
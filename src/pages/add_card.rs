@@ -1,19 +1,142 @@
 use dioxus::prelude::*;
 
-use crate::{components::CardEditor, services::database::use_database};
+use crate::{
+    components::{CardEditor, NoteEditor},
+    services::{database::*, errors::use_error_log},
+};
 
 #[allow(non_snake_case)]
 pub fn AddCard(cx: Scope) -> Element {
     let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let note_types = use_state(&cx, || {
+        errors.ok(db.borrow().get_note_types()).unwrap_or_default()
+    });
+    let note_type = use_state(&cx, || 0);
+    let tags = use_state(&cx, Vec::<String>::new);
+    // Changed after adding a card to recreate the editor with empty fields.
+    let form_key = use_state(&cx, || 0);
+    let similar = use_state(&cx, Vec::<SimilarCard>::new);
+    // Contents the user was warned about, saving them again adds them anyway.
+    let warned = use_state(&cx, Vec::<String>::new);
+
+    // Returns whether the contents can be added.
+    let check_duplicates = move |contents: Vec<String>| {
+        if *warned.current() == contents {
+            return true;
+        }
+
+        let found = contents
+            .iter()
+            .map(|content| db.borrow().find_similar_cards(content))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|found| found.into_iter().flatten().collect::<Vec<_>>());
+        let found = match errors.ok(found) {
+            Some(found) => found,
+            None => return false,
+        };
+        if found.is_empty() {
+            return true;
+        }
+
+        similar.set(found);
+        warned.set(contents);
+        false
+    };
+
+    let reset = move || {
+        similar.set(Vec::new());
+        warned.set(Vec::new());
+        form_key.set(**form_key + 1);
+    };
+
+    let key = **form_key;
+    let editor = match **note_type {
+        0 => cx.render(rsx! {
+            CardEditor {
+                key: "{key}",
+                tags: tags,
+                onsave: move |content: &str| {
+                    if !content.is_empty() && check_duplicates(vec![content.to_owned()]) {
+                        let added = db.borrow_mut().transaction(|db| {
+                            let id = db.create_card(content)?;
+                            db.set_card_tags(id, tags)
+                        });
+                        if errors.ok(added).is_some() {
+                            reset();
+                        }
+                    }
+                },
+            }
+        }),
+        _ => cx.render(rsx! {
+            note_types.iter().filter(|t| t.id == **note_type).map(|t| rsx! {
+                NoteEditor {
+                    key: "{t.id}-{key}",
+                    note_type: t.id,
+                    tags: tags,
+                    onsave: move |values: &[String]| {
+                        if values.iter().all(|v| v.is_empty()) {
+                            return;
+                        }
+
+                        let contents = match errors.ok(db.borrow().render_note(t.id, values)) {
+                            Some(rendered) => rendered
+                                .into_iter()
+                                .map(|(_, content)| content)
+                                .filter(|content| !content.trim().is_empty())
+                                .collect(),
+                            None => return,
+                        };
+                        if check_duplicates(contents) {
+                            let added = db.borrow_mut().transaction(|db| {
+                                let note = db.create_note(t.id, values)?;
+                                db.set_note_tags(note, tags)
+                            });
+                            if errors.ok(added).is_some() {
+                                reset();
+                            }
+                        }
+                    },
+                }
+            })
+        }),
+    };
 
     cx.render(rsx! {
         h1 { "Add card" }
-        CardEditor {
-            onsave: |content: &str| {
-                if !content.is_empty() {
-                    db.borrow_mut().create_card(content);
-                }
+        select {
+            onchange: |evt| {
+                note_type.set(evt.value.parse().unwrap_or(0));
             },
+            option { value: "0", "Plain" }
+            note_types.iter().map(|t| rsx! {
+                option {
+                    key: "{t.id}",
+                    value: "{t.id}",
+                    "{t.name}"
+                }
+            })
         }
+        (!similar.is_empty()).then(|| rsx! {
+            div {
+                class: "duplicate-warning",
+                p { "Similar cards already exist. Save again to add it anyway." }
+                ul {
+                    similar.iter().map(|s| {
+                        let percent = (s.similarity * 100.0).floor();
+                        let preview = s.card.content.lines().next().unwrap_or_default();
+                        rsx! {
+                            li {
+                                key: "{s.card.id}",
+                                "{percent}% "
+                                Link { to: "/edit_card/{s.card.id}", "{preview}" }
+                            }
+                        }
+                    })
+                }
+            }
+        })
+        editor
     })
 }
@@ -1,73 +1,700 @@
-use std::collections::HashSet;
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
+use chrono::{DateTime, Utc};
 use dioxus::prelude::*;
 
-use crate::{components::MarkdownView, services::database::*};
+use crate::{
+    components::{CardForm, MarkdownView},
+    services::{
+        database::*,
+        errors::{use_error_log, ErrorLog},
+        search::Query,
+        tag_tree::{self, TagNode},
+    },
+};
+
+const VISIBLE_ROWS: usize = 25;
+// Keep in sync with the row height in cards.css.
+const ROW_HEIGHT: f64 = 28.0;
+const DOM_DELTA_PIXEL: u32 = 0;
+const DOM_DELTA_LINE: u32 = 1;
 
 #[allow(non_snake_case)]
 pub fn Cards(cx: Scope) -> Element {
     let db = use_database(&cx);
-    let cards = use_state(&cx, || db.borrow().get_cards());
-    let tags = use_state(&cx, || db.borrow().get_tags());
-    let selected_tags = use_state(&cx, || HashSet::<usize>::new());
-    let show_tagless = use_state(&cx, || false);
+    let errors = use_error_log(&cx);
+    let tags = use_state(&cx, || {
+        tag_tree::build(&errors.ok(db.borrow().get_tags()).unwrap_or_default())
+    });
+    let filter = use_state(&cx, TagFilter::default);
+    let expanded_tags = use_state(&cx, HashSet::<Id>::new);
+    let search = use_state(&cx, String::new);
+    let sort = use_state(&cx, || None::<CardSort>);
+    let total = use_state(&cx, || {
+        errors
+            .ok(db
+                .borrow()
+                .count_matching_cards(&Query::default(), &TagFilter::default()))
+            .unwrap_or_default()
+    });
+    let first_row = use_state(&cx, || 0);
+    let results = use_state(&cx, || {
+        errors
+            .ok(db.borrow().search_card_summaries(
+                &Query::default(),
+                &TagFilter::default(),
+                None,
+                Page {
+                    offset: 0,
+                    limit: VISIBLE_ROWS,
+                },
+            ))
+            .unwrap_or_default()
+    });
+    let selected = use_state(&cx, || None::<Id>);
+    let checked = use_state(&cx, HashSet::<Id>::new);
+    let bulk_tag = use_state(&cx, String::new);
+    let reschedule_days = use_state(&cx, || "1".to_owned());
+    let duplicate_groups = use_state(&cx, || None::<Vec<Vec<Card>>>);
+    let finding_duplicates = use_state(&cx, || false);
+    let search_error = use_state(&cx, String::new);
+    let saved_searches = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_saved_searches())
+            .unwrap_or_default()
+    });
+    let save_name = use_state(&cx, String::new);
+    let saved_search = use_route(&cx)
+        .segment("id")
+        .and_then(|id| id.parse::<Id>().ok());
+    let source = use_state(&cx, || None::<Id>);
+    let update_any = &*cx.use_hook(|_| cx.schedule_update_any());
+
+    // Only the visible rows are loaded, the rest are fetched while scrolling.
+    let load_page = move || match Query::parse(&search.current()) {
+        Ok(query) => {
+            let db = db.borrow();
+            let filter = filter.current();
+            let page = db.count_matching_cards(&query, &filter).and_then(|count| {
+                let first = (*first_row.current()).min(count.saturating_sub(VISIBLE_ROWS));
+                let summaries = db.search_card_summaries(
+                    &query,
+                    &filter,
+                    *sort.current(),
+                    Page {
+                        offset: first,
+                        limit: VISIBLE_ROWS,
+                    },
+                )?;
+                Ok((count, first, summaries))
+            });
+            if let Some((count, first, summaries)) = errors.ok(page) {
+                results.set(summaries);
+                total.set(count);
+                first_row.set(first);
+            }
+            search_error.set(String::new());
+        }
+        Err(err) => {
+            search_error.set(err.to_string());
+        }
+    };
+
+    let reload = move || {
+        first_row.set(0);
+        load_page();
+    };
+
+    let reload_tags = move || {
+        if let Some(all) = errors.ok(db.borrow().get_tags()) {
+            tags.set(tag_tree::build(&all));
+        }
+    };
+
+    let reload_saved_searches = move || {
+        if let Some(all) = errors.ok(db.borrow().get_saved_searches()) {
+            saved_searches.set(all);
+        }
+        // Refresh the saved searches in the navigation.
+        update_any(ScopeId(0));
+    };
+
+    let run_bulk_action = move |action: BulkAction| {
+        let ids = checked.iter().copied().collect::<Vec<_>>();
+        let applied = db.borrow_mut().apply_bulk_action(&ids, &action);
+        if errors.ok(applied).is_none() {
+            return;
+        }
+
+        if action == BulkAction::Delete {
+            checked.set(HashSet::new());
+            selected.set(None);
+        }
+        reload_tags();
+        load_page();
+    };
+
+    // Comparing the cards of a large collection takes a while, so it runs in
+    // the background.
+    let find_duplicates = move || {
+        let db = cx.consume_context::<Rc<RefCell<Database>>>().unwrap();
+        let errors = cx.consume_context::<Rc<ErrorLog>>().unwrap();
+        let duplicate_groups = duplicate_groups.clone();
+        let finding_duplicates = finding_duplicates.clone();
+        finding_duplicates.set(true);
+        cx.spawn(async move {
+            duplicate_groups.set(errors.ok(find_duplicate_groups(&db).await));
+            finding_duplicates.set(false);
+        });
+    };
+
+    let scroll_to = move |row: usize| {
+        if row != *first_row.current() {
+            first_row.set(row);
+            load_page();
+        }
+    };
+
+    // Opening a saved search from the navigation keeps this page mounted.
+    if *source.get() != saved_search {
+        source.set(saved_search);
+        if let Some(id) = saved_search {
+            if let Some(saved) = errors.ok(db.borrow().get_saved_search(id)) {
+                search.set(saved.query);
+                save_name.set(saved.name);
+                reload();
+            }
+        }
+    }
+
+    let checked_count = checked.len();
+    let undo_description = db.borrow().get_undo_description().map(str::to_owned);
+    let undo_label = match &undo_description {
+        Some(description) => format!("Undo \"{description}\""),
+        None => "Undo".to_owned(),
+    };
+
+    let first_row_number = (*first_row.get() + 1).min(**total);
+    let last_row = (*first_row.get() + results.len()).min(**total);
+    let max_first_row = total.saturating_sub(VISIBLE_ROWS);
+
+    let header = |name: &'static str, column: SortColumn| {
+        let arrow = match *sort.get() {
+            Some(CardSort {
+                column: sorted,
+                descending,
+            }) if sorted == column => match descending {
+                true => " ▼",
+                false => " ▲",
+            },
+            _ => "",
+        };
+
+        rsx! {
+            th {
+                key: "{name}",
+                class: "sortable",
+                onclick: move |_| {
+                    // Cycles between ascending, descending and the default order.
+                    sort.set(match *sort.current() {
+                        Some(CardSort { column: sorted, descending: false }) if sorted == column => {
+                            Some(CardSort { column, descending: true })
+                        }
+                        Some(CardSort { column: sorted, descending: true }) if sorted == column => None,
+                        _ => Some(CardSort { column, descending: false }),
+                    });
+                    reload();
+                },
+                "{name}{arrow}"
+            }
+        }
+    };
+
+    let headers = [
+        ("Card", SortColumn::Content),
+        ("Tags", SortColumn::Tags),
+        ("Due", SortColumn::DueDate),
+        ("Interval", SortColumn::Interval),
+        ("Attempts", SortColumn::Attempts),
+        ("Ratio", SortColumn::Ratio),
+        ("Added", SortColumn::Created),
+        ("Edited", SortColumn::Modified),
+    ]
+    .into_iter()
+    .map(|(name, column)| header(name, column));
+
+    let rows = results.iter().map(|r| {
+        let id = r.id;
+        let preview = match &r.snippet {
+            Some(snippet) => highlight(snippet),
+            None => preview(&r.preview),
+        };
+        let tags = r.tags.join(", ");
+        let review = &r.review;
+        let ratio = match review.recall_attempts {
+            0 => "-".to_owned(),
+            attempts => format!(
+                "{:.0}%",
+                review.successful_recalls as f64 / attempts as f64 * 100.0
+            ),
+        };
+        let created_at = format_date(r.created_at);
+        let modified_at = format_date(r.modified_at);
+        let class = match (*selected.get() == Some(id), r.suspended) {
+            (true, true) => "selected suspended",
+            (true, false) => "selected",
+            (false, true) => "suspended",
+            (false, false) => "",
+        };
+        let is_checked = checked.contains(&id);
+
+        rsx! {
+            tr {
+                key: "{id}",
+                class: "{class}",
+                onclick: move |_| {
+                    selected.set(Some(id));
+                },
+                td {
+                    input {
+                        r#type: "checkbox",
+                        checked: "{is_checked}",
+                        onclick: move |evt| {
+                            // Checking a card shouldn't open it.
+                            evt.cancel_bubble();
+                            checked.with_mut(|checked| {
+                                if !checked.remove(&id) {
+                                    checked.insert(id);
+                                }
+                            });
+                        },
+                    }
+                }
+                td { dangerous_inner_html: "{preview}" }
+                td { "{tags}" }
+                td { "{review.due_date}" }
+                td { "{review.due_days}" }
+                td { "{review.recall_attempts}" }
+                td { "{ratio}" }
+                td { "{created_at}" }
+                td { "{modified_at}" }
+            }
+        }
+    });
+
+    // Keyed so that the form is recreated for every selected card.
+    let pane = selected.iter().map(|&id| {
+        let content = errors
+            .ok(db.borrow().get_card(id))
+            .map(|card| card.content)
+            .unwrap_or_default();
+        rsx! {
+            div {
+                key: "{id}",
+                h2 { "Preview" }
+                MarkdownView {
+                    text: "{content}",
+                }
+                h2 { "Edit" }
+                CardForm {
+                    id: id,
+                    onsave: move |_| {
+                        load_page();
+                    },
+                    ondelete: move |_| {
+                        selected.set(None);
+                        load_page();
+                    },
+                }
+                button {
+                    onclick: move |_| {
+                        selected.set(None);
+                    },
+                    "Close"
+                }
+            }
+        }
+    });
 
     cx.render(rsx! {
         h1 { "All Cards" }
+        input {
+            r#type: "search",
+            size: "60",
+            placeholder: "Search, e.g. tag:rust -tag:done due:<3 is:new added:7 \"exact phrase\"",
+            value: "{search}",
+            oninput: move |evt| {
+                search.set(evt.value.clone());
+                reload();
+            },
+        }
+        p { color: "red", "{search_error}" }
+        input {
+            placeholder: "Name",
+            value: "{save_name}",
+            oninput: move |evt| {
+                save_name.set(evt.value.clone());
+            },
+        }
+        button {
+            disabled: format_args!("{}", search.trim().is_empty() || save_name.trim().is_empty() || !search_error.is_empty()),
+            onclick: move |_| {
+                let saved = db.borrow_mut().save_search(save_name.trim(), search.trim());
+                errors.ok(saved);
+                reload_saved_searches();
+            },
+            "Save search"
+        }
+        ul {
+            saved_searches.iter().map(|saved| rsx! {
+                li {
+                    key: "{saved.id}",
+                    "{saved.name}: {saved.query} "
+                    button {
+                        onclick: move |_| {
+                            let deleted = db.borrow_mut().delete_saved_search(saved.id);
+                            errors.ok(deleted);
+                            reload_saved_searches();
+                        },
+                        "Delete"
+                    }
+                }
+            })
+        }
 
         h2 { "Tags" }
         button {
-            onclick: |_| {
-                show_tagless.set(false);
-                selected_tags.make_mut().clear();
-                cards.set(db.borrow().get_cards());
+            onclick: move |_| {
+                filter.set(TagFilter::default());
+                reload();
             },
             "Reset"
         }
-        br {}
+        p { "Click a tag to cycle between all of, any of, none of and unfiltered." }
         span {
-            color: format_args!("{}", if **show_tagless {"blue"} else {"black"}),
-            onclick: |_| {
-                let mut show = false;
-                show_tagless.with_mut(|s| {
-                    *s = !*s;
-                    show = *s;
-                });
-
-                if show {
-                    cards.set(db.borrow().get_cards_without_tags());
-                } else {
-                    cards.set(db.borrow().get_cards_with_tags(&selected_tags.current().iter().copied().collect::<Vec<_>>()));
-                }
+            color: format_args!("{}", if filter.tagless {"blue"} else {"black"}),
+            onclick: move |_| {
+                filter.make_mut().tagless ^= true;
+                reload();
             },
             "tagless",
         }
-        br {}
-        tags.iter().map(|t| rsx! {
-            span {
-                key: "{t.id}",
-                color: format_args!("{}", if !**show_tagless && selected_tags.contains(&t.id) {"blue"} else {"black"}),
-                onclick: |_| {
-                    show_tagless.set(false);
-                    selected_tags.with_mut(|tags| {
-                        if tags.contains(&t.id) {
-                            tags.remove(&t.id);
-                        } else {
-                            tags.insert(t.id);
+        ul {
+            class: "tag-tree",
+            tags.iter().map(|node| rsx! {
+                TagTreeItem {
+                    key: "{node.id}",
+                    node: node,
+                    filter: filter,
+                    expanded: expanded_tags,
+                    onselect: move |id| {
+                        filter.make_mut().toggle(id);
+                        reload();
+                    },
+                }
+            })
+        }
+
+        h2 { "Cards" }
+        p { "{total} cards" }
+        div {
+            class: "bulk-actions",
+            "{checked_count} selected "
+            button {
+                onclick: move |_| {
+                    if let Ok(query) = Query::parse(&search.current()) {
+                        let ids = db.borrow().search_card_ids(&query, &filter.current());
+                        if let Some(ids) = errors.ok(ids) {
+                            checked.set(ids.into_iter().collect());
                         }
-                    });
-                    cards.set(db.borrow().get_cards_with_tags(&selected_tags.current().iter().copied().collect::<Vec<_>>()));
+                    }
+                },
+                "Select all {total}"
+            }
+            button {
+                onclick: move |_| {
+                    checked.set(HashSet::new());
+                },
+                "Clear selection"
+            }
+            br {}
+            input {
+                placeholder: "Tag",
+                value: "{bulk_tag}",
+                oninput: move |evt| {
+                    bulk_tag.set(evt.value.clone());
+                },
+            }
+            button {
+                disabled: format_args!("{}", checked.is_empty() || bulk_tag.trim().is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::AddTag(bulk_tag.trim().to_owned()));
+                },
+                "Add tag"
+            }
+            button {
+                disabled: format_args!("{}", checked.is_empty() || bulk_tag.trim().is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::RemoveTag(bulk_tag.trim().to_owned()));
+                },
+                "Remove tag"
+            }
+            " Due in "
+            input {
+                r#type: "number",
+                min: "0",
+                size: "4",
+                value: "{reschedule_days}",
+                oninput: move |evt| {
+                    reschedule_days.set(evt.value.clone());
+                },
+            }
+            " days "
+            button {
+                disabled: format_args!("{}", checked.is_empty() || reschedule_days.parse::<usize>().is_err()),
+                onclick: move |_| {
+                    if let Ok(days) = reschedule_days.parse() {
+                        run_bulk_action(BulkAction::Reschedule(days));
+                    }
+                },
+                "Reschedule"
+            }
+            br {}
+            button {
+                disabled: format_args!("{}", checked.is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::ResetProgress);
+                },
+                "Reset progress"
+            }
+            button {
+                disabled: format_args!("{}", checked.is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::Suspend);
+                },
+                "Suspend"
+            }
+            button {
+                disabled: format_args!("{}", checked.is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::Unsuspend);
+                },
+                "Unsuspend"
+            }
+            button {
+                disabled: format_args!("{}", checked.is_empty()),
+                onclick: move |_| {
+                    run_bulk_action(BulkAction::Delete);
+                },
+                "Move to trash"
+            }
+            button {
+                disabled: format_args!("{}", undo_description.is_none()),
+                onclick: move |_| {
+                    let undone = db.borrow_mut().undo();
+                    errors.ok(undone);
+                    reload_tags();
+                    load_page();
                 },
-                "{t.name} \t",
+                "{undo_label}"
+            }
+        }
+        div {
+            class: "card-browser",
+            div {
+                class: "card-list",
+                table {
+                    class: "card-table",
+                    onwheel: move |evt| {
+                        let rows = match evt.delta_mode {
+                            DOM_DELTA_PIXEL => evt.delta_y / ROW_HEIGHT,
+                            DOM_DELTA_LINE => evt.delta_y,
+                            _ => evt.delta_y * VISIBLE_ROWS as f64,
+                        };
+                        // Small deltas from touchpads still move at least one row.
+                        let rows = rows.abs().ceil() as usize;
+                        let first = *first_row.current();
+                        scroll_to(match evt.delta_y < 0.0 {
+                            true => first.saturating_sub(rows),
+                            false => (first + rows).min(max_first_row),
+                        });
+                    },
+                    tr {
+                        th {}
+                        headers
+                    }
+                    rows
+                }
+                p {
+                    "{first_row_number}–{last_row} of {total} "
+                    input {
+                        r#type: "range",
+                        min: "0",
+                        max: "{max_first_row}",
+                        value: "{first_row}",
+                        oninput: move |evt| {
+                            if let Ok(row) = evt.value.parse() {
+                                scroll_to(row);
+                            }
+                        },
+                    }
+                }
+            }
+            div {
+                class: "card-pane",
+                pane
             }
+        }
+
+        h2 { "Duplicates" }
+        button {
+            disabled: format_args!("{}", **finding_duplicates),
+            onclick: move |_| {
+                find_duplicates();
+            },
+            "Find duplicates"
+        }
+        duplicate_groups.as_ref().map(|groups| match groups.is_empty() {
+            true => rsx! { p { "No duplicates found" } },
+            false => rsx! {
+                p { "Keeping a card adds the tags of the others to it and moves them to the trash." }
+                groups.iter().map(|group| {
+                    let ids = group.iter().map(|card| card.id).collect::<Vec<_>>();
+                    let first = ids[0];
+                    rsx! {
+                        div {
+                            key: "{first}",
+                            class: "duplicate-group",
+                            group.iter().map(|card| {
+                                let id = card.id;
+                                let ids = ids.clone();
+                                rsx! {
+                                    div {
+                                        key: "{id}",
+                                        MarkdownView {
+                                            text: "{card.content}",
+                                        }
+                                        button {
+                                            onclick: move |_| {
+                                                let merged = db.borrow_mut().merge_duplicate_cards(id, &ids);
+                                                errors.ok(merged);
+                                                find_duplicates();
+                                                reload_tags();
+                                                load_page();
+                                            },
+                                            "Keep this card"
+                                        }
+                                    }
+                                }
+                            })
+                        }
+                    }
+                })
+            },
         })
+    })
+}
 
-        h2 { "Cards" }
-        cards.iter().map(|c| rsx! {
-            MarkdownView {
-                key: "{c.id}",
-                text: "{c.content}",
+fn format_date(datetime: Option<DateTime<Utc>>) -> String {
+    datetime.map_or("-".to_owned(), |datetime| {
+        datetime.format("%Y-%m-%d").to_string()
+    })
+}
+
+// The first non-empty line of a card, shortened to fit in a table cell and
+// escaped so it can be shown like the highlighted search snippets.
+fn preview(content: &str) -> String {
+    const MAX_LENGTH: usize = 80;
+
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+
+    let line = match line.char_indices().nth(MAX_LENGTH) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line.to_owned(),
+    };
+
+    escape_html(&line)
+}
+
+// A search snippet with its matches marked.
+fn highlight(snippet: &str) -> String {
+    escape_html(snippet)
+        .replace(SNIPPET_START, "<mark>")
+        .replace(SNIPPET_END, "</mark>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[allow(non_snake_case)]
+fn TagTreeItem<'a>(cx: Scope<'a, TagTreeItemProps<'a>>) -> Element {
+    let node = cx.props.node;
+    let expanded = cx.props.expanded;
+    let is_expanded = expanded.contains(&node.id);
+    let (color, decoration) = match cx.props.filter.state_of(node.id) {
+        Some(TagFilterState::Include) => ("blue", "none"),
+        Some(TagFilterState::AnyOf) => ("green", "none"),
+        Some(TagFilterState::Exclude) => ("red", "line-through"),
+        None => ("black", "none"),
+    };
+
+    let toggle = match (node.children.is_empty(), is_expanded) {
+        (true, _) => "•",
+        (false, true) => "▾",
+        (false, false) => "▸",
+    };
+
+    cx.render(rsx! {
+        li {
+            span {
+                class: "tag-tree-toggle",
+                onclick: move |_| {
+                    expanded.with_mut(|expanded| {
+                        if !expanded.remove(&node.id) {
+                            expanded.insert(node.id);
+                        }
+                    });
+                },
+                "{toggle} "
             }
-        })
+            span {
+                color: "{color}",
+                text_decoration: "{decoration}",
+                onclick: move |_| {
+                    cx.props.onselect.call(node.id);
+                },
+                "{node.name}"
+            }
+            is_expanded.then(|| rsx! {
+                ul {
+                    node.children.iter().map(|child| rsx! {
+                        TagTreeItem {
+                            key: "{child.id}",
+                            node: child,
+                            filter: cx.props.filter,
+                            expanded: expanded,
+                            onselect: move |id| {
+                                cx.props.onselect.call(id);
+                            },
+                        }
+                    })
+                }
+            })
+        }
     })
 }
+
+#[derive(Props)]
+struct TagTreeItemProps<'a> {
+    node: &'a TagNode,
+    filter: &'a UseState<TagFilter>,
+    expanded: &'a UseState<HashSet<Id>>,
+    onselect: EventHandler<'a, Id>,
+}
@@ -0,0 +1,129 @@
+use dioxus::prelude::*;
+
+use crate::{
+    components::Button,
+    services::{
+        database::{use_database, CardConflict, Id},
+        errors::use_error_log,
+    },
+};
+
+#[allow(non_snake_case)]
+pub fn Conflicts(cx: Scope) -> Element {
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let conflicts = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_card_conflicts())
+            .unwrap_or_default()
+    });
+
+    let refresh = || {
+        if let Some(list) = errors.ok(db.borrow().get_card_conflicts()) {
+            conflicts.set(list);
+        }
+    };
+
+    if conflicts.is_empty() {
+        return cx.render(rsx! {
+            h1 { "Conflicts" }
+            p { "No conflicts" }
+        });
+    }
+
+    cx.render(rsx! {
+        h1 { "Conflicts" }
+        p {
+            "These cards were edited in both copies of the collection. "
+            "Until a version is picked they keep the content they have in each copy. "
+            "Cards made from a note become plain cards when they get another version."
+        }
+        conflicts.iter().map(|conflict| rsx! {
+            ConflictItem {
+                key: "{conflict.card}",
+                conflict: conflict,
+                onresolve: move |_| refresh(),
+            }
+        })
+    })
+}
+
+#[allow(non_snake_case)]
+fn ConflictItem<'a>(cx: Scope<'a, ConflictItemProps<'a>>) -> Element {
+    let conflict = cx.props.conflict;
+    let id = conflict.card;
+
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let card = use_state(&cx, || errors.ok(db.borrow().get_card(id)));
+    // Starts out as this copy's version.
+    let merged = use_state(&cx, || {
+        card.as_ref()
+            .map(|card| card.content.clone())
+            .unwrap_or_default()
+    });
+
+    let card = match card.get() {
+        Some(card) => card,
+        None => return None,
+    };
+    let modified_at = card.modified_at.map_or(String::new(), |modified_at| {
+        modified_at.format("%Y-%m-%d %H:%M").to_string()
+    });
+    let other_changed_at = conflict.changed_at.format("%Y-%m-%d %H:%M");
+
+    let resolve = move |content: &str| {
+        let resolved = db.borrow_mut().resolve_card_conflict(id, content);
+        if errors.ok(resolved).is_some() {
+            cx.props.onresolve.call(id);
+        }
+    };
+
+    cx.render(rsx! {
+        div {
+            class: "conflict",
+            h2 { "Card {id}" }
+            div {
+                class: "conflict-versions",
+                div {
+                    h3 { "This copy" }
+                    p { "Edited {modified_at}" }
+                    pre { "{card.content}" }
+                    Button {
+                        onclick: move |_| resolve(&card.content),
+                        name: "Keep this version",
+                    }
+                }
+                div {
+                    h3 { "Other copy" }
+                    p { "Edited {other_changed_at}" }
+                    pre { "{conflict.content}" }
+                    Button {
+                        onclick: move |_| resolve(&conflict.content),
+                        name: "Keep this version",
+                    }
+                }
+            }
+            h3 { "Merge by hand" }
+            textarea {
+                rows: "10",
+                cols: "80",
+                value: "{merged}",
+                oninput: move |evt| {
+                    merged.set(evt.value.clone());
+                },
+            }
+            br {}
+            Button {
+                onclick: move |_| resolve(merged.get()),
+                name: "Save merged version",
+            }
+        }
+    })
+}
+
+#[derive(Props)]
+struct ConflictItemProps<'a> {
+    conflict: &'a CardConflict,
+    onresolve: EventHandler<'a, Id>,
+}
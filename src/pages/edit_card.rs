@@ -1,6 +1,6 @@
 use dioxus::prelude::*;
 
-use crate::{components::CardEditor, services::database::*};
+use crate::components::{CardForm, CardHistory};
 
 // TODO: Go back when done.
 
@@ -14,25 +14,40 @@ pub fn EditCard(cx: Scope) -> Element {
 
     assert!(id != 0);
 
-    let db = use_database(&cx);
-    let done = use_state(&cx, || false);
+    let done = use_state(&cx, || None::<&str>);
+    // Changed to recreate the form with the restored content.
+    let form_key = use_state(&cx, || 0);
 
-    if *done.current() {
+    if let Some(message) = *done.current() {
         return cx.render(rsx! {
-            h1 { "Done" }
+            h1 { "{message}" }
         });
     }
 
+    let card_form = std::iter::once(**form_key).map(|key| {
+        rsx! {
+            CardForm {
+                key: "{key}",
+                id: id,
+                onsave: move |_| {
+                    done.set(Some("Done"));
+                },
+                ondelete: move |_| {
+                    done.set(Some("Moved to trash"));
+                },
+            }
+        }
+    });
+
     cx.render(rsx! {
         h1 { "Edit card" }
         p { "Id: {id}" }
-        CardEditor {
-            initial_value: db.borrow().get_card(id).content,
-            onsave: move |content: &str| {
-                if !content.is_empty() {
-                    db.borrow_mut().update_card_content(id, content);
-                    done.set(true);
-                }
+        card_form
+        h2 { "History" }
+        CardHistory {
+            id: id,
+            onrestore: move |_| {
+                form_key.set(**form_key + 1);
             },
         }
     })
@@ -1,11 +1,17 @@
 mod add_card;
 mod cards;
+mod conflicts;
 mod edit_card;
 mod review;
 mod settings;
+mod tags;
+mod trash;
 
 pub use add_card::*;
 pub use cards::*;
+pub use conflicts::*;
 pub use edit_card::*;
 pub use review::*;
 pub use settings::*;
+pub use tags::*;
+pub use trash::*;
@@ -2,12 +2,53 @@ use std::cell::Cell;
 
 use dioxus::prelude::*;
 
-use crate::{components::MarkdownView, services::database::*};
+use crate::{
+    components::MarkdownView,
+    services::{database::*, errors::use_error_log, search::Query},
+};
 
 #[allow(non_snake_case)]
 pub fn Review(cx: Scope) -> Element {
     let db = use_database(&cx);
-    let cards = use_ref(&cx, || db.borrow().get_due_cards());
+    let errors = use_error_log(&cx);
+    let saved_search = use_route(&cx)
+        .segment("id")
+        .and_then(|id| id.parse::<Id>().ok());
+    let source = use_state(&cx, || saved_search);
+    let cards = use_ref(&cx, || {
+        errors
+            .ok(get_review_cards(&db.borrow(), saved_search))
+            .unwrap_or_default()
+    });
+
+    let index = &*cx.use_hook(|_| Cell::new(0));
+    let show_count = &*cx.use_hook(|_| Cell::new(1));
+    let show_amount = &*cx.use_hook(|_| Cell::new(cards.read().first().map_or(0, split_count)));
+    let show_content = use_state(&cx, || {
+        cards
+            .read()
+            .first()
+            .map_or(String::new(), |card| split_content(card, show_count.get()))
+    });
+
+    // Switching between saved searches keeps this page mounted.
+    if *source.get() != saved_search {
+        source.set(saved_search);
+        cards.set(
+            errors
+                .ok(get_review_cards(&db.borrow(), saved_search))
+                .unwrap_or_default(),
+        );
+        index.set(0);
+        show_count.set(1);
+        show_amount.set(cards.read().first().map_or(0, split_count));
+        show_content.set(
+            cards
+                .read()
+                .first()
+                .map_or(String::new(), |card| split_content(card, show_count.get())),
+        );
+    }
 
     if cards.read().is_empty() {
         return cx.render(rsx! {
@@ -15,19 +56,15 @@ pub fn Review(cx: Scope) -> Element {
         });
     }
 
-    let index = &*cx.use_hook(|_| Cell::new(0));
-    let show_count = &*cx.use_hook(|_| Cell::new(1));
-    let show_amount = &*cx.use_hook(|_| Cell::new(split_count(&cards.read()[index.get()])));
-    let show_content = use_state(&cx, || {
-        split_content(&cards.read()[index.get()], show_count.get())
-    });
-
     let is_card_fully_shown = show_count.get() == show_amount.get();
     let review_buttons = match is_card_fully_shown {
         true => rsx! {
             button {
                 onclick: move |_| {
-                    update_card_review(&cards.read()[index.get()], true, &mut *db.borrow_mut());
+                    let updated = update_card_review(&cards.read()[index.get()], true, &mut *db.borrow_mut());
+                    if errors.ok(updated).is_none() {
+                        return;
+                    }
                     cards.write_silent().swap_remove(index.get());
                     cards.with(|cards|{
                         if !cards.is_empty() {
@@ -44,7 +81,10 @@ pub fn Review(cx: Scope) -> Element {
             }
             button {
                 onclick: move |_| {
-                    update_card_review(&cards.read()[index.get()], false, &mut *db.borrow_mut());
+                    let updated = update_card_review(&cards.read()[index.get()], false, &mut *db.borrow_mut());
+                    if errors.ok(updated).is_none() {
+                        return;
+                    }
                     cards.write_silent().swap_remove(index.get());
                     cards.with(|cards|{
                         if !cards.is_empty() {
@@ -89,6 +129,16 @@ pub fn Review(cx: Scope) -> Element {
     })
 }
 
+fn get_review_cards(db: &Database, saved_search: Option<Id>) -> Result<Vec<Card>, DatabaseError> {
+    match saved_search {
+        Some(id) => match Query::parse(&db.get_saved_search(id)?.query) {
+            Ok(query) => db.get_due_cards_matching(&query),
+            Err(_) => Ok(Vec::new()),
+        },
+        None => db.get_due_cards(),
+    }
+}
+
 // FIXME: Parse <hr> tags properly with an html parser or something.
 fn split_content(card: &Card, count: usize) -> String {
     let mut split = split_iter(card);
@@ -108,7 +158,7 @@ fn split_iter(card: &Card) -> std::str::Split<&str> {
     card.content.split("---")
 }
 
-fn update_card_review(card: &Card, success: bool, db: &mut Database) {
+fn update_card_review(card: &Card, success: bool, db: &mut Database) -> Result<(), DatabaseError> {
     let mut review = card.review.clone();
     review.recall_attempts += 1;
     if success {
@@ -120,5 +170,5 @@ fn update_card_review(card: &Card, success: bool, db: &mut Database) {
     review.due_date = (chrono::Utc::now() + chrono::Duration::days(review.due_days as i64))
         .date()
         .naive_utc();
-    db.update_card_review(card.id, review);
+    db.update_card_review(card.id, review)
 }
@@ -1,17 +1,53 @@
 use dioxus::prelude::*;
 use native_dialog::FileDialog;
 
-use crate::services::config::use_config;
+use crate::services::{
+    backup::{create_backup, list_backups},
+    config::use_config,
+    database::use_database,
+    errors::use_error_log,
+    sync::SyncReport,
+};
 
 #[allow(non_snake_case)]
 pub fn Settings(cx: Scope) -> Element {
     let cfg = use_config(&cx);
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
     let location = use_state(&cx, || {
         cfg.borrow()
             .get_location()
             .map_or("None".to_string(), |loc| loc.display().to_string())
     });
 
+    let sync_status = use_state(&cx, || describe_sync(db.borrow().get_last_sync()));
+    let sync_server = use_state(&cx, || {
+        cfg.borrow()
+            .get_sync_server()
+            .unwrap_or_default()
+            .to_owned()
+    });
+    let sync_user = use_state(&cx, || cfg.borrow().get_sync_user().to_owned());
+    let sync_password = use_state(&cx, || cfg.borrow().get_sync_password().to_owned());
+
+    let retention_days = use_state(&cx, || cfg.borrow().get_trash_retention_days().to_string());
+    let autosave_minutes = use_state(&cx, || cfg.borrow().get_autosave_minutes().to_string());
+    let autosave_changes = use_state(&cx, || cfg.borrow().get_autosave_changes().to_string());
+    let backup_days = use_state(&cx, || cfg.borrow().get_backup_days().to_string());
+    let backup_weeks = use_state(&cx, || cfg.borrow().get_backup_weeks().to_string());
+
+    let backups = use_state(&cx, || {
+        errors.ok(list_backups(&cfg.borrow())).unwrap_or_default()
+    });
+    // The backup waiting for the user to confirm the restore.
+    let restoring = use_state(&cx, || None);
+
+    let refresh_backups = || {
+        if let Some(list) = errors.ok(list_backups(&cfg.borrow())) {
+            backups.set(list);
+        }
+    };
+
     cx.render(rsx! {
         h1 { "Settings" }
 
@@ -23,15 +59,217 @@ pub fn Settings(cx: Scope) -> Element {
             onclick: move |_| {
                 let path = FileDialog::new()
                     .add_filter("Rustyzip", &["rustyzip"])
-                    .show_save_single_file()
-                    .unwrap();
+                    .show_save_single_file();
 
-                if let Some(path) = &path {
+                if let Some(Some(path)) = &errors.ok(path) {
                     cfg.borrow_mut().set_location(path);
                     location.set(path.display().to_string());
                 }
             },
             "Change"
         }
+        h3 { "Sync" }
+        p {
+            "The collection at the location is merged with this one when the app starts. "
+            "With a sync server set, changes are exchanged with the server instead."
+        }
+        label {
+            "Sync server "
+            input {
+                placeholder: "http://localhost:8517",
+                value: "{sync_server}",
+                oninput: move |evt| {
+                    sync_server.set(evt.value.clone());
+                    cfg.borrow_mut().set_sync_server(&evt.value);
+                },
+            }
+        }
+        br {}
+        label {
+            "User "
+            input {
+                value: "{sync_user}",
+                oninput: move |evt| {
+                    sync_user.set(evt.value.clone());
+                    cfg.borrow_mut().set_sync_user(&evt.value);
+                },
+            }
+        }
+        br {}
+        label {
+            "Password "
+            input {
+                r#type: "password",
+                value: "{sync_password}",
+                oninput: move |evt| {
+                    sync_password.set(evt.value.clone());
+                    cfg.borrow_mut().set_sync_password(&evt.value);
+                },
+            }
+        }
+        br {}
+        small { "The password is stored unencrypted in the app's settings file." }
+        br {}
+        span { "{sync_status}" }
+        br {}
+        button {
+            onclick: move |_| {
+                let synced = db.borrow_mut().sync(&cfg.borrow()).map(describe_sync);
+                if let Some(status) = errors.ok(synced) {
+                    sync_status.set(status);
+                    // Writes this copy's changes to the location.
+                    let saved = db.borrow_mut().save(&cfg.borrow());
+                    errors.ok(saved);
+                }
+            },
+            "Sync now"
+        }
+
+        h2 { "Trash" }
+        label {
+            "Delete cards permanently after "
+            input {
+                r#type: "number",
+                min: "0",
+                value: "{retention_days}",
+                oninput: move |evt| {
+                    retention_days.set(evt.value.clone());
+                    if let Ok(days) = evt.value.parse() {
+                        cfg.borrow_mut().set_trash_retention_days(days);
+                    }
+                },
+            }
+            " days (0 to keep them forever)"
+        }
+
+        h2 { "Autosave" }
+        label {
+            "Save every "
+            input {
+                r#type: "number",
+                min: "0",
+                value: "{autosave_minutes}",
+                oninput: move |evt| {
+                    autosave_minutes.set(evt.value.clone());
+                    if let Ok(minutes) = evt.value.parse() {
+                        cfg.borrow_mut().set_autosave_minutes(minutes);
+                    }
+                },
+            }
+            " minutes (0 to turn off)"
+        }
+        br {}
+        label {
+            "Save after "
+            input {
+                r#type: "number",
+                min: "0",
+                value: "{autosave_changes}",
+                oninput: move |evt| {
+                    autosave_changes.set(evt.value.clone());
+                    if let Ok(changes) = evt.value.parse() {
+                        cfg.borrow_mut().set_autosave_changes(changes);
+                    }
+                },
+            }
+            " changes (0 to turn off)"
+        }
+        p { "The collection is also saved when the app is closed." }
+
+        h2 { "Backups" }
+        p { "The collection is backed up the first time it is saved each day." }
+        label {
+            "Keep daily backups for "
+            input {
+                r#type: "number",
+                min: "0",
+                value: "{backup_days}",
+                oninput: move |evt| {
+                    backup_days.set(evt.value.clone());
+                    if let Ok(days) = evt.value.parse() {
+                        cfg.borrow_mut().set_backup_days(days);
+                    }
+                },
+            }
+            " days"
+        }
+        br {}
+        label {
+            "Keep weekly backups for "
+            input {
+                r#type: "number",
+                min: "0",
+                value: "{backup_weeks}",
+                oninput: move |evt| {
+                    backup_weeks.set(evt.value.clone());
+                    if let Ok(weeks) = evt.value.parse() {
+                        cfg.borrow_mut().set_backup_weeks(weeks);
+                    }
+                },
+            }
+            " weeks (0 for both turns backups off)"
+        }
+        br {}
+        button {
+            onclick: move |_| {
+                errors.ok(create_backup(&cfg.borrow()));
+                refresh_backups();
+            },
+            "Back up now"
+        }
+        table {
+            tr {
+                th { "Date" }
+                th { "Cards" }
+                th {}
+            }
+            backups.iter().map(|backup| {
+                let path = backup.path.clone();
+                let key = path.display().to_string();
+                let created_at = backup.created_at.format("%Y-%m-%d %H:%M");
+                let cards = backup.cards.map_or("?".to_owned(), |cards| cards.to_string());
+                let restore_cell = if restoring.get().as_ref() == Some(&path) {
+                    rsx! {
+                        "Replace the current collection with this backup? "
+                        button {
+                            onclick: move |_| {
+                                let restored = db.borrow_mut().restore_backup(&cfg.borrow(), &path);
+                                errors.ok(restored);
+                                restoring.set(None);
+                                refresh_backups();
+                            },
+                            "Restore"
+                        }
+                        button {
+                            onclick: move |_| restoring.set(None),
+                            "Cancel"
+                        }
+                    }
+                } else {
+                    rsx! {
+                        button {
+                            onclick: move |_| restoring.set(Some(path.clone())),
+                            "Restore"
+                        }
+                    }
+                };
+
+                rsx! {
+                    tr {
+                        key: "{key}",
+                        td { "{created_at}" }
+                        td { "{cards}" }
+                        td { restore_cell }
+                    }
+                }
+            })
+        }
     })
 }
+
+fn describe_sync(report: Option<&SyncReport>) -> String {
+    match report {
+        Some(report) => format!("Received: {}. Sent: {}.", report.received, report.sent),
+        None => "Not synced yet.".to_owned(),
+    }
+}
@@ -0,0 +1,181 @@
+use dioxus::prelude::*;
+
+use crate::services::{database::*, errors::use_error_log, tag_tree};
+
+#[allow(non_snake_case)]
+pub fn Tags(cx: Scope) -> Element {
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let tags = use_state(&cx, || {
+        errors.ok(db.borrow().get_tag_counts()).unwrap_or_default()
+    });
+    let new_name = use_state(&cx, String::new);
+    let renaming = use_state(&cx, || None::<(Id, String)>);
+    let merge_target = use_state(&cx, || None::<(Id, Id)>);
+    let error = use_state(&cx, String::new);
+
+    let refresh = || {
+        if let Some(counts) = errors.ok(db.borrow().get_tag_counts()) {
+            tags.set(counts);
+        }
+        renaming.set(None);
+        merge_target.set(None);
+        error.set(String::new());
+    };
+
+    cx.render(rsx! {
+        h1 { "Tags" }
+
+        h2 { "Create" }
+        input {
+            value: "{new_name}",
+            oninput: |evt| {
+                new_name.set(evt.value.clone());
+            },
+        }
+        button {
+            onclick: move |_| {
+                let name = new_name.trim();
+                if name.is_empty() {
+                    return;
+                }
+
+                match errors.ok(db.borrow().get_tag_by_name(name)) {
+                    Some(None) => {}
+                    Some(Some(_)) => {
+                        error.set(format!("Tag \"{name}\" already exists"));
+                        return;
+                    }
+                    None => return,
+                }
+
+                let created = db.borrow_mut().create_tag(name);
+                if errors.ok(created).is_some() {
+                    new_name.set(String::new());
+                }
+                refresh();
+            },
+            "Create"
+        }
+        p { color: "red", "{error}" }
+
+        h2 { "All tags" }
+        table {
+            tr {
+                th { "Name" }
+                th { "Cards" }
+                th { "Rename" }
+                th { "Merge into" }
+                th {}
+            }
+            tags.iter().map(|t| {
+                let id = t.tag.id;
+                let name_cell = match renaming.get() {
+                    Some((renaming_id, name)) if *renaming_id == id => rsx! {
+                        input {
+                            value: "{name}",
+                            oninput: move |evt| {
+                                renaming.set(Some((id, evt.value.clone())));
+                            },
+                        }
+                    },
+                    _ => {
+                        let indent = tag_tree::depth(&t.tag.name) * 16;
+                        let leaf_name = tag_tree::leaf_name(&t.tag.name);
+                        rsx! {
+                            span {
+                                padding_left: "{indent}px",
+                                "{leaf_name}"
+                            }
+                        }
+                    }
+                };
+                let rename_cell = match renaming.get() {
+                    Some((renaming_id, name)) if *renaming_id == id => rsx! {
+                        button {
+                            onclick: move |_| {
+                                let name = name.trim();
+                                if name.is_empty() {
+                                    return;
+                                }
+
+                                match errors.ok(db.borrow().is_tag_rename_conflicting(id, name)) {
+                                    Some(false) => {}
+                                    Some(true) => {
+                                        error.set(format!("Tag \"{name}\" already exists, merge instead"));
+                                        return;
+                                    }
+                                    None => return,
+                                }
+
+                                let renamed = db.borrow_mut().update_tag_name(id, name);
+                                errors.ok(renamed);
+                                refresh();
+                            },
+                            "Save"
+                        }
+                        button {
+                            onclick: move |_| {
+                                renaming.set(None);
+                            },
+                            "Cancel"
+                        }
+                    },
+                    _ => rsx! {
+                        button {
+                            onclick: move |_| {
+                                renaming.set(Some((id, t.tag.name.clone())));
+                            },
+                            "Rename"
+                        }
+                    },
+                };
+
+                rsx! {
+                    tr {
+                        key: "{id}",
+                        td { name_cell }
+                        td { "{t.card_count}" }
+                        td { rename_cell }
+                        td {
+                            select {
+                                onchange: move |evt| {
+                                    merge_target.set(evt.value.parse().ok().map(|into| (id, into)));
+                                },
+                                option { value: "", "" }
+                                tags.iter().filter(|other| other.tag.id != id && !tag_tree::is_descendant(&other.tag.name, &t.tag.name)).map(|other| rsx! {
+                                    option {
+                                        key: "{other.tag.id}",
+                                        value: "{other.tag.id}",
+                                        "{other.tag.name}"
+                                    }
+                                })
+                            }
+                            button {
+                                disabled: format_args!("{}", !matches!(merge_target.get(), Some((from, _)) if *from == id)),
+                                onclick: move |_| {
+                                    if let Some((from, into)) = *merge_target.get() {
+                                        let merged = db.borrow_mut().merge_tags(from, into);
+                                        errors.ok(merged);
+                                        refresh();
+                                    }
+                                },
+                                "Merge"
+                            }
+                        }
+                        td {
+                            button {
+                                onclick: move |_| {
+                                    let deleted = db.borrow_mut().delete_tag(id);
+                                    errors.ok(deleted);
+                                    refresh();
+                                },
+                                "Delete"
+                            }
+                        }
+                    }
+                }
+            })
+        }
+    })
+}
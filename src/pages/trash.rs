@@ -0,0 +1,95 @@
+use dioxus::prelude::*;
+
+use crate::{
+    components::MarkdownView,
+    services::{config::use_config, database::*, errors::use_error_log},
+};
+
+#[allow(non_snake_case)]
+pub fn Trash(cx: Scope) -> Element {
+    let cfg = use_config(&cx);
+    let db = use_database(&cx);
+    let errors = use_error_log(&cx);
+    let cards = use_state(&cx, || {
+        errors
+            .ok(db.borrow().get_deleted_cards())
+            .unwrap_or_default()
+    });
+
+    let refresh = || {
+        if let Some(deleted) = errors.ok(db.borrow().get_deleted_cards()) {
+            cards.set(deleted);
+        }
+    };
+
+    let retention = match cfg.borrow().get_trash_retention_days() {
+        0 => "Cards stay in the trash until it is emptied.".to_owned(),
+        days => format!("Cards are deleted permanently after {days} days in the trash."),
+    };
+
+    cx.render(rsx! {
+        h1 { "Trash" }
+        p { "{retention}" }
+        button {
+            disabled: format_args!("{}", cards.is_empty()),
+            onclick: move |_| {
+                let emptied = db.borrow_mut().empty_trash();
+                errors.ok(emptied);
+                refresh();
+            },
+            "Empty trash"
+        }
+        table {
+            tr {
+                th { "Card" }
+                th { "Tags" }
+                th { "Deleted" }
+                th {}
+            }
+            cards.iter().map(|card| {
+                let id = card.id;
+                let tags = errors
+                    .ok(db.borrow().get_tags_for_card(id))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|t| t.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let deleted_at = card
+                    .deleted_at
+                    .map_or(String::new(), |deleted_at| deleted_at.format("%Y-%m-%d %H:%M").to_string());
+
+                rsx! {
+                    tr {
+                        key: "{id}",
+                        td {
+                            MarkdownView {
+                                text: "{card.content}",
+                            }
+                        }
+                        td { "{tags}" }
+                        td { "{deleted_at}" }
+                        td {
+                            button {
+                                onclick: move |_| {
+                                    let restored = db.borrow_mut().restore_card(id);
+                                    errors.ok(restored);
+                                    refresh();
+                                },
+                                "Restore"
+                            }
+                            button {
+                                onclick: move |_| {
+                                    let purged = db.borrow_mut().purge_card(id);
+                                    errors.ok(purged);
+                                    refresh();
+                                },
+                                "Delete permanently"
+                            }
+                        }
+                    }
+                }
+            })
+        }
+    })
+}
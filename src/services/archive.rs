@@ -1,75 +1,170 @@
 use std::{
+    fmt,
     fs::File,
-    io::{Read, Write},
-    path::Path,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
 };
 
-use zip::write::FileOptions;
+use tempfile::NamedTempFile;
+use zip::{result::ZipError, write::FileOptions};
 
 pub use zip::{ZipArchive, ZipWriter};
 
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Zip(ZipError),
+    NotADirectory(PathBuf),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(err) => write!(f, "{err}"),
+            ArchiveError::Zip(err) => write!(f, "{err}"),
+            ArchiveError::NotADirectory(path) => {
+                write!(f, "{} is not a directory", path.display())
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> Self {
+        ArchiveError::Io(err)
+    }
+}
+
+impl From<ZipError> for ArchiveError {
+    fn from(err: ZipError) -> Self {
+        ArchiveError::Zip(err)
+    }
+}
+
+// Writes the archive to a temporary file next to `target` and renames it over
+// `target` only once it has been read back, so a crash or a full disk leaves
+// the old archive intact.
+pub fn write_archive<P, F>(target: P, f: F) -> Result<(), ArchiveError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut ZipWriter<File>) -> Result<(), ArchiveError>,
+{
+    let target = target.as_ref();
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Dropping the temporary file on error deletes it.
+    let temp_file = NamedTempFile::new_in(dir)?;
+
+    let mut writer = ZipWriter::new(temp_file.as_file().try_clone()?);
+    f(&mut writer)?;
+    writer.finish()?.sync_all()?;
+
+    verify_archive(temp_file.reopen()?)?;
+
+    temp_file.persist(target).map_err(|err| err.error)?;
+    Ok(())
+}
+
+// Reads every entry, which checks their checksums.
+fn verify_archive(file: File) -> Result<(), ArchiveError> {
+    let mut reader = ZipArchive::new(file)?;
+    for i in 0..reader.len() {
+        io::copy(&mut reader.by_index(i)?, &mut io::sink())?;
+    }
+    Ok(())
+}
+
 pub trait ZipWriterExt {
-    fn write_file<P: AsRef<Path>>(&mut self, file: P, name: &str);
-    fn write_dir<P: AsRef<Path>>(&mut self, dir: P, name: &str);
+    fn write_file<P: AsRef<Path>>(&mut self, file: P, name: &str) -> Result<(), ArchiveError>;
+    fn write_dir<P: AsRef<Path>>(&mut self, dir: P, name: &str) -> Result<(), ArchiveError>;
 }
 
 pub trait ZipReaderExt {
-    fn read_file(&mut self, name: &str) -> Vec<u8>;
-    fn extract_file<P: AsRef<Path>>(&mut self, name: &str, target: P, replace: bool);
-    fn extract_dir<P: AsRef<Path>>(&mut self, name: &str, target: P, replace: bool);
+    fn read_file(&mut self, name: &str) -> Result<Vec<u8>, ArchiveError>;
+    fn extract_file<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        target: P,
+        replace: bool,
+    ) -> Result<(), ArchiveError>;
+    fn extract_dir<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        target: P,
+        replace: bool,
+    ) -> Result<(), ArchiveError>;
 }
 
 impl ZipWriterExt for ZipWriter<File> {
-    fn write_file<P: AsRef<Path>>(&mut self, file: P, name: &str) {
-        self.start_file(name, FileOptions::default()).unwrap();
-        let bytes = std::fs::read(file).unwrap();
-        self.write_all(&bytes).unwrap();
+    fn write_file<P: AsRef<Path>>(&mut self, file: P, name: &str) -> Result<(), ArchiveError> {
+        self.start_file(name, FileOptions::default())?;
+        let bytes = std::fs::read(file)?;
+        self.write_all(&bytes)?;
+        Ok(())
     }
 
-    fn write_dir<P: AsRef<Path>>(&mut self, dir: P, name: &str) {
-        self.add_directory(name, FileOptions::default()).unwrap();
-        for entry in std::fs::read_dir(dir).unwrap() {
-            let file_path = entry.unwrap().path();
-            let file_name = file_path.file_name().unwrap();
+    fn write_dir<P: AsRef<Path>>(&mut self, dir: P, name: &str) -> Result<(), ArchiveError> {
+        self.add_directory(name, FileOptions::default())?;
+        for entry in std::fs::read_dir(dir)? {
+            let file_path = entry?.path();
+            let file_name = match file_path.file_name() {
+                Some(file_name) => file_name,
+                None => continue,
+            };
             let target = Path::new(name)
                 .join(file_name)
                 .to_string_lossy()
                 .replace("\\", "/");
-            self.write_file(&file_path, &target);
+            self.write_file(&file_path, &target)?;
         }
+        Ok(())
     }
 }
 
 impl ZipReaderExt for ZipArchive<File> {
-    fn read_file(&mut self, name: &str) -> Vec<u8> {
-        self.by_name(name)
-            .unwrap()
-            .bytes()
-            .filter_map(|b| b.ok())
-            .collect()
+    fn read_file(&mut self, name: &str) -> Result<Vec<u8>, ArchiveError> {
+        let mut bytes = Vec::new();
+        self.by_name(name)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
     }
 
-    fn extract_file<P: AsRef<Path>>(&mut self, name: &str, target: P, replace: bool) {
+    fn extract_file<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        target: P,
+        replace: bool,
+    ) -> Result<(), ArchiveError> {
         if !replace && target.as_ref().exists() {
-            return;
+            return Ok(());
         }
 
-        if let Ok(mut zip_file) = self.by_name(name) {
-            let mut target = std::fs::File::create(target).unwrap();
-            std::io::copy(&mut zip_file, &mut target).unwrap();
+        match self.by_name(name) {
+            Ok(mut zip_file) => {
+                let mut target = std::fs::File::create(target)?;
+                std::io::copy(&mut zip_file, &mut target)?;
+                Ok(())
+            }
+            Err(ZipError::FileNotFound) => Ok(()),
+            Err(err) => Err(err.into()),
         }
     }
 
-    fn extract_dir<P: AsRef<Path>>(&mut self, name: &str, target: P, replace: bool) {
+    fn extract_dir<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        target: P,
+        replace: bool,
+    ) -> Result<(), ArchiveError> {
         if !target.as_ref().is_dir() {
-            panic!();
+            return Err(ArchiveError::NotADirectory(target.as_ref().to_owned()));
         }
 
-        std::fs::create_dir_all(target.as_ref()).unwrap();
         let dir = Some(Path::new(name));
 
         for i in 0..self.len() {
-            let mut zip_file = self.by_index(i).unwrap();
+            let mut zip_file = self.by_index(i)?;
 
             let zip_path = match zip_file.enclosed_name() {
                 Some(path) => path,
@@ -77,16 +172,21 @@ impl ZipReaderExt for ZipArchive<File> {
             };
 
             if zip_path.parent() == dir {
-                let file_name = zip_path.file_name().unwrap();
+                let file_name = match zip_path.file_name() {
+                    Some(file_name) => file_name,
+                    None => continue,
+                };
                 let file = target.as_ref().join(file_name);
 
                 if !replace && file.exists() {
                     continue;
                 }
 
-                let mut target = std::fs::File::create(file).unwrap();
-                std::io::copy(&mut zip_file, &mut target).unwrap();
+                let mut target = std::fs::File::create(file)?;
+                std::io::copy(&mut zip_file, &mut target)?;
             }
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,92 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use dioxus::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::{config::Config, database::Database, errors::ErrorLog};
+
+// How often the autosave conditions are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Saving after a number of changes waits at least this long between saves,
+// so that a burst of edits doesn't rewrite the archive every second.
+const MIN_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// How long closing the window waits for the last save.
+const CLOSE_SAVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The database lives on the thread that runs the app, so closing the window
+// asks that thread to save and waits for the reply.
+pub type CloseRequests = UnboundedReceiver<mpsc::Sender<()>>;
+
+// Asks for the last save when dropped. The desktop config holds on to it until
+// the window has closed, before the process exits.
+pub struct SaveOnClose(UnboundedSender<mpsc::Sender<()>>);
+
+impl Drop for SaveOnClose {
+    fn drop(&mut self) {
+        let (done, saved) = mpsc::channel();
+        if self.0.send(done).is_ok() {
+            saved.recv_timeout(CLOSE_SAVE_TIMEOUT).ok();
+        }
+    }
+}
+
+pub fn save_on_close() -> (SaveOnClose, CloseRequests) {
+    let (sender, requests) = unbounded_channel();
+    (SaveOnClose(sender), requests)
+}
+
+// Returns whether both the config and the collection were saved.
+pub fn save_all(cfg: &RefCell<Config>, db: &RefCell<Database>, errors: &ErrorLog) -> bool {
+    let cfg_saved = errors.ok(cfg.borrow_mut().save()).is_some();
+    let db_saved = errors.ok(db.borrow_mut().save(&cfg.borrow())).is_some();
+    cfg_saved && db_saved
+}
+
+// Saves on a timer, after a number of changes and when the window closes,
+// however it was closed.
+pub fn use_autosave(cx: &ScopeState, close_requests: Option<CloseRequests>) {
+    use_future(cx, (), |_| {
+        let cfg = cx.consume_context::<Rc<RefCell<Config>>>().unwrap();
+        let db = cx.consume_context::<Rc<RefCell<Database>>>().unwrap();
+        let errors = cx.consume_context::<Rc<ErrorLog>>().unwrap();
+        let mut close_requests = close_requests;
+
+        async move {
+            let mut last_save = Instant::now();
+
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+
+                if let Some(done) = close_requests.as_mut().and_then(|r| r.try_recv().ok()) {
+                    save_all(&cfg, &db, &errors);
+                    done.send(()).ok();
+                    continue;
+                }
+
+                let minutes = cfg.borrow().get_autosave_minutes();
+                let changes = cfg.borrow().get_autosave_changes();
+                let unsaved = db.borrow().get_unsaved_changes();
+                let has_changes = unsaved > 0 || cfg.borrow().has_unsaved_changes();
+
+                let timer_due = minutes > 0
+                    && has_changes
+                    && last_save.elapsed() >= Duration::from_secs(minutes as u64 * 60);
+                let changes_due =
+                    changes > 0 && unsaved >= changes && last_save.elapsed() >= MIN_SAVE_INTERVAL;
+
+                // Failed saves are retried on the next interval.
+                if timer_due || changes_due {
+                    save_all(&cfg, &db, &errors);
+                    last_save = Instant::now();
+                }
+            }
+        }
+    });
+}
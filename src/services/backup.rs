@@ -0,0 +1,153 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use rusqlite::{Connection, OpenFlags};
+use tempfile::NamedTempFile;
+
+use super::{archive::*, config::Config, database::DatabaseError};
+
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_SUFFIX: &str = ".rustyzip";
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d-%H%M%S%3f";
+
+#[derive(Debug)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    // `None` if the backup couldn't be read.
+    pub cards: Option<usize>,
+}
+
+// Backs up the collection if there is no backup from today yet and removes
+// the backups that are no longer kept.
+pub fn backup_if_due(cfg: &Config) -> Result<(), DatabaseError> {
+    if cfg.get_backup_days() == 0 && cfg.get_backup_weeks() == 0 {
+        return Ok(());
+    }
+
+    let today = Utc::now().date();
+    let backups = backup_files(cfg)?;
+    if !backups
+        .iter()
+        .any(|(_, created_at)| created_at.date() == today)
+    {
+        create_backup(cfg)?;
+    }
+
+    prune_backups(cfg)
+}
+
+pub fn create_backup(cfg: &Config) -> Result<PathBuf, DatabaseError> {
+    let dir = cfg.get_backups_dir();
+    fs::create_dir_all(&dir)?;
+
+    let file_name = format!(
+        "{BACKUP_PREFIX}{}{BACKUP_SUFFIX}",
+        Utc::now().format(TIMESTAMP_FORMAT)
+    );
+    let path = dir.join(file_name);
+
+    write_archive(&path, |writer| {
+        writer.write_file(cfg.get_db_file(), cfg.get_db_file_name())?;
+        writer.write_dir(cfg.get_assets_dir(), cfg.get_assets_dir_name())
+    })?;
+
+    Ok(path)
+}
+
+// Newest first.
+pub fn list_backups(cfg: &Config) -> Result<Vec<Backup>, DatabaseError> {
+    let backups = backup_files(cfg)?
+        .into_iter()
+        .map(|(path, created_at)| {
+            let cards = count_cards(cfg, &path).ok();
+            Backup {
+                path,
+                created_at,
+                cards,
+            }
+        })
+        .collect();
+
+    Ok(backups)
+}
+
+// Keeps the newest backup of each of the last `backup_days` days and of each
+// of the last `backup_weeks` weeks that have backups.
+fn prune_backups(cfg: &Config) -> Result<(), DatabaseError> {
+    let mut days = Vec::new();
+    let mut weeks = Vec::new();
+
+    for (path, created_at) in backup_files(cfg)? {
+        let day = created_at.date();
+        let week = created_at.iso_week();
+        let mut keep = false;
+
+        if !days.contains(&day) && days.len() < cfg.get_backup_days() {
+            days.push(day);
+            keep = true;
+        }
+        if !weeks.contains(&week) && weeks.len() < cfg.get_backup_weeks() {
+            weeks.push(week);
+            keep = true;
+        }
+
+        if !keep {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Newest first.
+fn backup_files(cfg: &Config) -> Result<Vec<(PathBuf, DateTime<Utc>)>, DatabaseError> {
+    let dir = cfg.get_backups_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Some(created_at) = parse_backup_time(&path) {
+            files.push((path, created_at));
+        }
+    }
+    files.sort_by_key(|(_, created_at)| std::cmp::Reverse(*created_at));
+
+    Ok(files)
+}
+
+fn parse_backup_time(path: &Path) -> Option<DateTime<Utc>> {
+    let timestamp = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_SUFFIX)?;
+    let datetime = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    Some(DateTime::from_utc(datetime, Utc))
+}
+
+fn count_cards(cfg: &Config, path: &Path) -> Result<usize, DatabaseError> {
+    let mut reader = ZipArchive::new(File::open(path)?).map_err(ArchiveError::from)?;
+    let db_bytes = reader.read_file(cfg.get_db_file_name())?;
+    let mut temp_db_file = NamedTempFile::new_in(cfg.get_app_dir())?;
+    temp_db_file.write_all(&db_bytes)?;
+
+    let conn = Connection::open_with_flags(temp_db_file.path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // Backups from before the trash existed have no `deleted_at` column.
+    let count = conn
+        .query_row(
+            "SELECT COUNT(*) FROM cards WHERE deleted_at IS NULL",
+            [],
+            |row| row.get(0),
+        )
+        .or_else(|_| conn.query_row("SELECT COUNT(*) FROM cards", [], |row| row.get(0)))?;
+
+    Ok(count)
+}
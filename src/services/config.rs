@@ -1,6 +1,9 @@
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::{
     cell::RefCell,
-    io::Write,
+    fmt,
+    io::{self, Write},
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -13,10 +16,62 @@ pub fn use_config(cx: &ScopeState) -> &RefCell<Config> {
     &*cx.use_hook(|_| cx.consume_context::<Rc<RefCell<Config>>>().unwrap())
 }
 
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "Could not access the config file: {err}"),
+            ConfigError::Parse(err) => write!(f, "The config file is invalid: {err}"),
+            ConfigError::Serialize(err) => write!(f, "Could not write the config file: {err}"),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(err: toml::ser::Error) -> Self {
+        ConfigError::Serialize(err)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     version: usize,
     location: Option<PathBuf>,
+    // Trashed cards are purged after this many days, 0 keeps them forever.
+    trash_retention_days: usize,
+    // The collection is saved this often and after this many changes, 0
+    // turns either off.
+    autosave_minutes: usize,
+    autosave_changes: usize,
+    // Number of daily and weekly backups to keep, 0 for both turns backups
+    // off.
+    backup_days: usize,
+    backup_weeks: usize,
+    // With a sync server set, changes are exchanged with it instead of the
+    // collection at the location.
+    sync_server: Option<String>,
+    sync_user: String,
+    // Kept as plain text, so the config file is only readable by its owner.
+    // Use a password for the sync server that isn't used anywhere else.
+    sync_password: String,
 
     #[serde(skip)]
     app_dir: PathBuf,
@@ -29,6 +84,14 @@ impl Default for Config {
         Self {
             version: 1,
             location: None,
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            autosave_minutes: DEFAULT_AUTOSAVE_MINUTES,
+            autosave_changes: DEFAULT_AUTOSAVE_CHANGES,
+            backup_days: DEFAULT_BACKUP_DAYS,
+            backup_weeks: DEFAULT_BACKUP_WEEKS,
+            sync_server: None,
+            sync_user: String::new(),
+            sync_password: String::new(),
             app_dir: std::env::current_dir().unwrap(),
             is_dirty: false,
         }
@@ -36,21 +99,21 @@ impl Default for Config {
 }
 
 impl Config {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, ConfigError> {
         let mut cfg = Self::default();
 
         let assets_dir = cfg.get_assets_dir();
         if !assets_dir.exists() {
-            std::fs::create_dir(assets_dir).unwrap();
+            std::fs::create_dir(assets_dir)?;
         }
 
         let cfg_file = cfg.get_config_file();
         if !cfg_file.exists() {
-            return cfg;
+            return Ok(cfg);
         }
 
-        let data = std::fs::read_to_string(cfg_file).unwrap();
-        let value: Value = toml::from_str(&data).unwrap();
+        let data = std::fs::read_to_string(cfg_file)?;
+        let value: Value = toml::from_str(&data)?;
 
         if let Value::Table(table) = value {
             if let Some(version) = table.get("version") {
@@ -65,6 +128,46 @@ impl Config {
                                     }
                                 }
                             }
+                            if let Some(days) = table.get("trash_retention_days") {
+                                if let Some(days) = days.as_integer() {
+                                    cfg.trash_retention_days = days.max(0) as usize;
+                                }
+                            }
+                            if let Some(minutes) = table.get("autosave_minutes") {
+                                if let Some(minutes) = minutes.as_integer() {
+                                    cfg.autosave_minutes = minutes.max(0) as usize;
+                                }
+                            }
+                            if let Some(changes) = table.get("autosave_changes") {
+                                if let Some(changes) = changes.as_integer() {
+                                    cfg.autosave_changes = changes.max(0) as usize;
+                                }
+                            }
+                            if let Some(days) = table.get("backup_days") {
+                                if let Some(days) = days.as_integer() {
+                                    cfg.backup_days = days.max(0) as usize;
+                                }
+                            }
+                            if let Some(weeks) = table.get("backup_weeks") {
+                                if let Some(weeks) = weeks.as_integer() {
+                                    cfg.backup_weeks = weeks.max(0) as usize;
+                                }
+                            }
+                            if let Some(server) = table.get("sync_server") {
+                                if let Some(server) = server.as_str() {
+                                    cfg.sync_server = Some(server.to_owned());
+                                }
+                            }
+                            if let Some(user) = table.get("sync_user") {
+                                if let Some(user) = user.as_str() {
+                                    cfg.sync_user = user.to_owned();
+                                }
+                            }
+                            if let Some(password) = table.get("sync_password") {
+                                if let Some(password) = password.as_str() {
+                                    cfg.sync_password = password.to_owned();
+                                }
+                            }
                         }
                         _ => {}
                     }
@@ -72,6 +175,17 @@ impl Config {
             }
         }
 
+        Ok(cfg)
+    }
+
+    // A new config for a collection kept in `dir`.
+    #[cfg(test)]
+    pub fn in_dir(dir: &Path) -> Self {
+        let cfg = Self {
+            app_dir: dir.to_owned(),
+            ..Self::default()
+        };
+        std::fs::create_dir_all(cfg.get_assets_dir()).unwrap();
         cfg
     }
 
@@ -91,6 +205,10 @@ impl Config {
         self.app_dir.join(ASSETS_DIR_NAME)
     }
 
+    pub fn get_backups_dir(&self) -> PathBuf {
+        self.app_dir.join(BACKUPS_DIR_NAME)
+    }
+
     pub fn get_location(&self) -> Option<PathBuf> {
         self.location.to_owned()
     }
@@ -100,18 +218,105 @@ impl Config {
         self.is_dirty = true;
     }
 
+    pub fn get_trash_retention_days(&self) -> usize {
+        self.trash_retention_days
+    }
+
+    pub fn set_trash_retention_days(&mut self, days: usize) {
+        self.trash_retention_days = days;
+        self.is_dirty = true;
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.is_dirty
+    }
+
+    pub fn get_autosave_minutes(&self) -> usize {
+        self.autosave_minutes
+    }
+
+    pub fn set_autosave_minutes(&mut self, minutes: usize) {
+        self.autosave_minutes = minutes;
+        self.is_dirty = true;
+    }
+
+    pub fn get_autosave_changes(&self) -> usize {
+        self.autosave_changes
+    }
+
+    pub fn set_autosave_changes(&mut self, changes: usize) {
+        self.autosave_changes = changes;
+        self.is_dirty = true;
+    }
+
+    pub fn get_backup_days(&self) -> usize {
+        self.backup_days
+    }
+
+    pub fn set_backup_days(&mut self, days: usize) {
+        self.backup_days = days;
+        self.is_dirty = true;
+    }
+
+    pub fn get_backup_weeks(&self) -> usize {
+        self.backup_weeks
+    }
+
+    pub fn set_backup_weeks(&mut self, weeks: usize) {
+        self.backup_weeks = weeks;
+        self.is_dirty = true;
+    }
+
+    pub fn get_sync_server(&self) -> Option<&str> {
+        self.sync_server.as_deref()
+    }
+
+    // An empty address turns the sync server off.
+    pub fn set_sync_server(&mut self, url: &str) {
+        let url = url.trim();
+        self.sync_server = (!url.is_empty()).then(|| url.to_owned());
+        self.is_dirty = true;
+    }
+
+    pub fn get_sync_user(&self) -> &str {
+        &self.sync_user
+    }
+
+    pub fn set_sync_user(&mut self, user: &str) {
+        self.sync_user = user.to_owned();
+        self.is_dirty = true;
+    }
+
+    pub fn get_sync_password(&self) -> &str {
+        &self.sync_password
+    }
+
+    pub fn set_sync_password(&mut self, password: &str) {
+        self.sync_password = password.to_owned();
+        self.is_dirty = true;
+    }
+
     pub const fn get_assets_dir_name(&self) -> &str {
         ASSETS_DIR_NAME
     }
 
-    pub fn save(&self) {
+    pub fn save(&mut self) -> Result<(), ConfigError> {
         if !self.is_dirty {
-            return;
+            return Ok(());
         }
 
-        let toml = toml::to_string(self).unwrap();
-        let mut file = std::fs::File::create(self.get_config_file()).unwrap();
-        file.write_all(toml.as_bytes()).unwrap();
+        let toml = toml::to_string(self)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(self.get_config_file())?;
+        // A file written before keeps its permissions when opened.
+        #[cfg(unix)]
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        file.write_all(toml.as_bytes())?;
+        self.is_dirty = false;
+        Ok(())
     }
 
     fn get_config_file(&self) -> PathBuf {
@@ -132,3 +337,15 @@ const DB_FILE_NAME: &str = "dev.db";
 const DB_FILE_NAME: &str = "rustycard.db";
 
 const ASSETS_DIR_NAME: &str = "assets";
+
+const BACKUPS_DIR_NAME: &str = "backups";
+
+const DEFAULT_TRASH_RETENTION_DAYS: usize = 30;
+
+const DEFAULT_AUTOSAVE_MINUTES: usize = 5;
+
+const DEFAULT_AUTOSAVE_CHANGES: usize = 20;
+
+const DEFAULT_BACKUP_DAYS: usize = 7;
+
+const DEFAULT_BACKUP_WEEKS: usize = 4;
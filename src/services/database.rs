@@ -1,23 +1,111 @@
-use std::{cell::RefCell, fs::File, io::Write, path::Path, rc::Rc};
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+};
 
 use chrono::{DateTime, NaiveDate, Utc};
 use dioxus::prelude::ScopeState;
-use rusqlite::{params, params_from_iter, Connection, OpenFlags, Params, Row};
-
-use super::{archive::*, config::Config};
+use rusqlite::{
+    params, params_from_iter, types::Value, Connection, OpenFlags, OptionalExtension, Params, Row,
+};
+use rustycard_sync::{SyncClient, SyncClientError};
+use tokio::sync::oneshot;
+
+use super::{
+    archive::*,
+    backup,
+    config::Config,
+    duplicates,
+    search::{self, CardState, Clause, Query, Term},
+    sync::{self, SyncChanges, SyncReport},
+    tag_tree, template,
+};
 
 pub type Id = usize;
 
+// Migration `i` upgrades a database from version `i + 1` to `i + 2`. Each one
+// runs in its own transaction together with the version update.
+const MIGRATIONS: [&str; 12] = [
+    include_str!("migrations/v2.sql"),
+    include_str!("migrations/v3.sql"),
+    include_str!("migrations/v4.sql"),
+    include_str!("migrations/v5.sql"),
+    include_str!("migrations/v6.sql"),
+    include_str!("migrations/v7.sql"),
+    include_str!("migrations/v8.sql"),
+    include_str!("migrations/v9.sql"),
+    include_str!("migrations/v10.sql"),
+    include_str!("migrations/v11.sql"),
+    include_str!("migrations/v12.sql"),
+    include_str!("migrations/v13.sql"),
+];
+
+// Name of the archive with the changes pushed to the sync server.
+const CHANGES_FILE_NAME: &str = "changes.rustyzip";
+
+// Number of bulk actions that can be undone.
+const UNDO_LIMIT: usize = 20;
+
+pub const DATABASE_VERSION: usize = MIGRATIONS.len() + 1;
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Sql(rusqlite::Error),
+    Io(io::Error),
+    Archive(ArchiveError),
+    NotFound,
+    // The database was written by a newer version of the app.
+    UnsupportedVersion(usize),
+    Backup(rusqlite::Error),
+    Migration(usize, rusqlite::Error),
+    SyncServer(SyncClientError),
+    // Note cards are rendered from their note, so their revisions can't be
+    // restored.
+    NoteCardRevision,
+    // Work done on another thread stopped before it finished.
+    BackgroundTask,
+}
+
 pub struct Database {
     connection: Connection,
     is_dirty: bool,
+    // Changes since the last save, a transaction counts as one.
+    unsaved_changes: usize,
+    undo_stack: Vec<UndoEntry>,
+    // Number of open `transaction` calls and whether they wrote anything.
+    transaction_depth: usize,
+    transaction_changed: bool,
+    last_sync: Option<SyncReport>,
+}
+
+// What a bulk action changed, restored on undo.
+struct UndoEntry {
+    description: String,
+    action: BulkAction,
+    // Each card with the values of the action's columns before and after it.
+    cards: Vec<(Id, Vec<Value>, Vec<Value>)>,
+    // The cards that had the tag before a tag action.
+    tagged: Vec<Id>,
 }
 
 #[derive(Debug)]
 pub struct Card {
+    // `id` is only valid in this copy of the collection, `guid` in all of
+    // them.
     pub id: Id,
+    pub guid: String,
     pub content: String,
     pub review: CardReview,
+    pub note: Option<Id>,
+    pub suspended: bool,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone)]
@@ -30,97 +118,504 @@ pub struct CardReview {
 
 #[derive(Debug)]
 pub struct Tag {
+    pub id: Id,
+    pub guid: String,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct SimilarCard {
+    pub card: Card,
+    pub similarity: f64,
+}
+
+// The content of a card before an edit.
+#[derive(Debug)]
+pub struct CardRevision {
+    pub id: Id,
+    pub card: Id,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// The other copy's version of a card that was edited in both copies.
+#[derive(Debug)]
+pub struct CardConflict {
+    pub card: Id,
+    pub content: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct SavedSearch {
+    pub id: Id,
+    pub name: String,
+    pub query: String,
+}
+
+// Control characters that don't show up in card text, so that the snippet can
+// be escaped before the matches are highlighted.
+pub const SNIPPET_START: char = '\u{2}';
+pub const SNIPPET_END: char = '\u{3}';
+
+// A card row without its full content, cheap enough to list thousands of.
+// `snippet` is only set when the query searched the card text. It is the raw
+// text with the matches between `SNIPPET_START` and `SNIPPET_END`.
+#[derive(Debug)]
+pub struct CardSummary {
+    pub id: Id,
+    pub preview: String,
+    pub snippet: Option<String>,
+    pub tags: Vec<String>,
+    pub review: CardReview,
+    pub note: Option<Id>,
+    pub suspended: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Content,
+    Tags,
+    DueDate,
+    Interval,
+    Attempts,
+    Ratio,
+    Created,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardSort {
+    pub column: SortColumn,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkAction {
+    AddTag(String),
+    RemoveTag(String),
+    // Makes the cards due this many days from today.
+    Reschedule(usize),
+    ResetProgress,
+    Suspend,
+    Unsuspend,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFilterState {
+    Include,
+    AnyOf,
+    Exclude,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    pub tags: Vec<(Id, TagFilterState)>,
+    pub tagless: bool,
+}
+
+#[derive(Debug)]
+pub struct TagCount {
+    pub tag: Tag,
+    pub card_count: usize,
+}
+
+#[derive(Debug)]
+pub struct NoteType {
+    pub id: Id,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct NoteField {
+    pub id: Id,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct NoteTemplate {
     pub id: Id,
     pub name: String,
+    pub content: String,
+}
+
+#[derive(Debug)]
+pub struct Note {
+    pub id: Id,
+    pub note_type: Id,
+    pub values: Vec<String>,
+}
+
+// A sync with the server, split so that the exchange with the server can run
+// on another thread while the app goes on.
+pub struct ServerSync {
+    client: SyncClient,
+    server: String,
+    pulled: u64,
+    pushed_at: String,
+    now: String,
+    changes: Vec<u8>,
+    sent: SyncChanges,
+    app_dir: PathBuf,
+}
+
+// The changes a `ServerSync` pulled, in order, and the number the server gave
+// the ones it pushed.
+pub struct ServerExchange {
+    sync: ServerSync,
+    pulled: Vec<(u64, tempfile::NamedTempFile)>,
+    pushed: Option<u64>,
+}
+
+impl CardSort {
+    fn to_sql(self) -> String {
+        let column = match self.column {
+            SortColumn::Content => "cards.content",
+            SortColumn::Tags => "tag_names",
+            SortColumn::DueDate => "cards.due_date",
+            SortColumn::Interval => "cards.due_days",
+            SortColumn::Attempts => "cards.recall_attempts",
+            SortColumn::Ratio => {
+                "CAST(cards.successful_recalls AS REAL) / NULLIF(cards.recall_attempts, 0)"
+            }
+            SortColumn::Created => "cards.created_at",
+            SortColumn::Modified => "cards.modified_at",
+        };
+
+        match self.descending {
+            true => format!("{column} DESC, cards.card_id DESC"),
+            false => format!("{column} ASC, cards.card_id ASC"),
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Sql(err) => write!(f, "Database error: {err}"),
+            DatabaseError::Io(err) => write!(f, "Could not access the database file: {err}"),
+            DatabaseError::Archive(err) => write!(f, "Could not read or write the collection: {err}"),
+            DatabaseError::NotFound => write!(f, "The item no longer exists."),
+            DatabaseError::UnsupportedVersion(version) => write!(
+                f,
+                "The database has version {version}, but this app only supports versions up to {DATABASE_VERSION}. Please update the app."
+            ),
+            DatabaseError::Backup(err) => {
+                write!(f, "Could not back up the database before upgrading it: {err}")
+            }
+            DatabaseError::Migration(version, err) => {
+                write!(f, "Could not upgrade the database to version {version}: {err}")
+            }
+            DatabaseError::SyncServer(err) => write!(f, "{err}"),
+            DatabaseError::NoteCardRevision => write!(
+                f,
+                "Earlier versions of a note's card can't be restored. Edit the note instead."
+            ),
+            DatabaseError::BackgroundTask => {
+                write!(f, "The task running in the background stopped unexpectedly.")
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => DatabaseError::NotFound,
+            err => DatabaseError::Sql(err),
+        }
+    }
+}
+
+impl From<io::Error> for DatabaseError {
+    fn from(err: io::Error) -> Self {
+        DatabaseError::Io(err)
+    }
+}
+
+impl From<ArchiveError> for DatabaseError {
+    fn from(err: ArchiveError) -> Self {
+        DatabaseError::Archive(err)
+    }
+}
+
+impl From<SyncClientError> for DatabaseError {
+    fn from(err: SyncClientError) -> Self {
+        DatabaseError::SyncServer(err)
+    }
+}
+
+impl fmt::Display for BulkAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkAction::AddTag(name) => write!(f, "Add tag \"{name}\""),
+            BulkAction::RemoveTag(name) => write!(f, "Remove tag \"{name}\""),
+            BulkAction::Reschedule(days) => write!(f, "Reschedule in {days} days"),
+            BulkAction::ResetProgress => write!(f, "Reset progress"),
+            BulkAction::Suspend => write!(f, "Suspend"),
+            BulkAction::Unsuspend => write!(f, "Unsuspend"),
+            BulkAction::Delete => write!(f, "Move to trash"),
+        }
+    }
+}
+
+impl BulkAction {
+    // The card columns the action writes.
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            BulkAction::AddTag(_) | BulkAction::RemoveTag(_) => &[],
+            BulkAction::Reschedule(_) => &["due_date"],
+            BulkAction::ResetProgress => &[
+                "due_date",
+                "due_days",
+                "recall_attempts",
+                "successful_recalls",
+            ],
+            BulkAction::Suspend | BulkAction::Unsuspend => &["suspended"],
+            BulkAction::Delete => &["deleted_at"],
+        }
+    }
+}
+
+impl ServerSync {
+    // Pulls what the other copies pushed since the last sync and pushes the
+    // changes made here. Doesn't touch the database.
+    pub fn exchange(self) -> Result<ServerExchange, DatabaseError> {
+        let mut pulled = Vec::new();
+        let mut last = self.pulled;
+        loop {
+            let page = self.client.pull(last)?;
+            if page.is_empty() {
+                break;
+            }
+            for pushed in page {
+                let mut file = tempfile::NamedTempFile::new_in(&self.app_dir)?;
+                file.write_all(&pushed.bytes)?;
+                last = pushed.seq;
+                pulled.push((pushed.seq, file));
+            }
+        }
+
+        let pushed = match self.sent.is_empty() {
+            true => None,
+            false => Some(self.client.push(&self.changes)?),
+        };
+        Ok(ServerExchange {
+            sync: self,
+            pulled,
+            pushed,
+        })
+    }
+}
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && !self.tagless
+    }
+
+    pub fn state_of(&self, id: Id) -> Option<TagFilterState> {
+        self.tags.iter().find(|t| t.0 == id).map(|t| t.1)
+    }
+
+    // Cycles through include, any of, exclude and back to unfiltered.
+    pub fn toggle(&mut self, id: Id) {
+        let next = match self.state_of(id) {
+            None => Some(TagFilterState::Include),
+            Some(TagFilterState::Include) => Some(TagFilterState::AnyOf),
+            Some(TagFilterState::AnyOf) => Some(TagFilterState::Exclude),
+            Some(TagFilterState::Exclude) => None,
+        };
+
+        match next {
+            Some(state) => match self.tags.iter_mut().find(|t| t.0 == id) {
+                Some(tag) => tag.1 = state,
+                None => self.tags.push((id, state)),
+            },
+            None => self.tags.retain(|t| t.0 != id),
+        }
+    }
 }
 
-trait FromRow {
-    fn from_row(row: &Row) -> Self;
+trait FromRow: Sized {
+    fn from_row(row: &Row) -> rusqlite::Result<Self>;
 }
 
 pub fn use_database(cx: &ScopeState) -> &RefCell<Database> {
     &*cx.use_hook(|_| cx.consume_context::<Rc<RefCell<Database>>>().unwrap())
 }
 
+// Syncs like `Database::sync`, but exchanges the changes with a sync server on
+// another thread, so that the app isn't held up by the network.
+pub async fn sync_in_background(
+    cfg: &RefCell<Config>,
+    db: &RefCell<Database>,
+) -> Result<(), DatabaseError> {
+    let sync = match db.borrow().start_server_sync(&cfg.borrow())? {
+        Some(sync) => sync,
+        None => return db.borrow_mut().sync(&cfg.borrow()).map(|_| ()),
+    };
+
+    let exchange = in_background(move || sync.exchange()).await??;
+    db.borrow_mut()
+        .finish_server_sync(&cfg.borrow(), exchange)
+        .map(|_| ())
+}
+
+// Groups the cards whose contents are similar, comparing them on another
+// thread so that the app isn't held up by a large collection.
+pub async fn find_duplicate_groups(
+    db: &RefCell<Database>,
+) -> Result<Vec<Vec<Card>>, DatabaseError> {
+    let contents = db.borrow().get_contents()?;
+    let groups = in_background(move || {
+        let contents = contents
+            .into_iter()
+            .map(|(id, content)| (id, duplicates::normalize(&content)))
+            .collect::<Vec<_>>();
+        duplicates::group(&contents)
+    })
+    .await?;
+
+    // Cards deleted in the meantime are left out.
+    let db = db.borrow();
+    let mut found = Vec::new();
+    for group in groups {
+        let mut cards = Vec::new();
+        for id in group {
+            match db.get_card(id) {
+                Ok(card) if card.deleted_at.is_none() => cards.push(card),
+                Ok(_) | Err(DatabaseError::NotFound) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if cards.len() > 1 {
+            found.push(cards);
+        }
+    }
+    Ok(found)
+}
+
+// Runs `f` on another thread and waits for it without blocking the app.
+async fn in_background<T, F>(f: F) -> Result<T, DatabaseError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || sender.send(f()).ok());
+    receiver.await.map_err(|_| DatabaseError::BackgroundTask)
+}
+
 impl Database {
-    pub fn new(cfg: &Config) -> Self {
-        if let Some(location) = cfg.get_location() {
-            if let Ok(file) = std::fs::File::open(location) {
-                let mut reader = ZipArchive::new(file).unwrap();
-                let temp_db_bytes = reader.read_file(cfg.get_db_file_name());
-                let mut temp_db_file = tempfile::NamedTempFile::new_in(cfg.get_app_dir()).unwrap();
-                temp_db_file.write_all(&temp_db_bytes).unwrap();
-
-                if Self::is_newer(temp_db_file.path(), &cfg.get_db_file()) {
-                    reader.extract_file(cfg.get_db_file_name(), cfg.get_db_file(), true);
-                    reader.extract_dir(cfg.get_assets_dir_name(), cfg.get_assets_dir(), false);
+    pub fn new(cfg: &Config) -> Result<Self, DatabaseError> {
+        // On a new machine the saved collection is taken as it is, otherwise
+        // it is merged in by `sync`.
+        if !cfg.get_db_file().exists() {
+            if let Some(location) = cfg.get_location() {
+                if let Ok(file) = std::fs::File::open(location) {
+                    let mut reader = ZipArchive::new(file).map_err(ArchiveError::from)?;
+                    reader.extract_file(cfg.get_db_file_name(), cfg.get_db_file(), true)?;
+                    reader.extract_dir(cfg.get_assets_dir_name(), cfg.get_assets_dir(), false)?;
                 }
             }
         }
 
-        let conn = match Connection::open(&cfg.get_db_file()) {
-            Ok(conn) => conn,
-            Err(err) => panic!("{err}"),
-        };
-
-        let mut db = Self {
-            connection: conn,
-            is_dirty: false,
-        };
+        let mut db = Self::with_connection(open_connection(&cfg.get_db_file())?);
 
         match db.try_get_version() {
             Some(version) => {
-                match version {
-                    // Current version
-                    1 => {}
-                    // Unknown version
-                    _ => {
-                        panic!("Unknown database version");
-                    }
-                }
+                let backup = cfg
+                    .get_app_dir()
+                    .join(format!("{}.v{version}.bak", cfg.get_db_file_name()));
+                db.migrate(version, Some(&backup))?;
             }
             None => {
                 // New database
-                db.write_batch(include_str!("schema.sql"));
+                db.write_batch(include_str!("schema.sql"))?;
+                db.migrate(1, None)?;
+                // Its example cards were never in another copy, so they are
+                // only matched by guid like any new card.
+                db.write_batch("UPDATE cards SET guid_hint = NULL")?;
             }
         }
 
-        db
+        if cfg.get_trash_retention_days() > 0 {
+            db.purge_trash_older_than(cfg.get_trash_retention_days())?;
+        }
+
+        Ok(db)
     }
 
-    fn is_newer<P: AsRef<Path>>(db_file: P, other_db_file: P) -> bool {
-        if !other_db_file.as_ref().exists() {
-            return true;
+    // Upgrades the database from `version` to the current version, copying it
+    // to `backup` first.
+    fn migrate(&mut self, version: usize, backup: Option<&Path>) -> Result<(), DatabaseError> {
+        if version > DATABASE_VERSION {
+            return Err(DatabaseError::UnsupportedVersion(version));
+        }
+        if version == DATABASE_VERSION {
+            return Ok(());
         }
 
-        if let Some(datetime) = Self::try_read_last_modified(db_file.as_ref()) {
-            if let Some(other_datetime) = Self::try_read_last_modified(other_db_file.as_ref()) {
-                return datetime > other_datetime;
+        if let Some(backup) = backup {
+            // `VACUUM INTO` fails if the file exists.
+            if backup.exists() {
+                std::fs::remove_file(backup).ok();
             }
+            self.connection
+                .execute("VACUUM INTO ?", [backup.to_string_lossy()])
+                .map_err(DatabaseError::Backup)?;
         }
 
-        false
-    }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version - 1) {
+            let target = i + 2;
+            let tx = self
+                .connection
+                .transaction()
+                .map_err(|err| DatabaseError::Migration(target, err))?;
+
+            // Dropping the transaction on error rolls it back.
+            tx.execute_batch(migration)
+                .and_then(|_| {
+                    tx.execute(
+                        "UPDATE metadata SET version = ? WHERE metadata_id = 1",
+                        [target],
+                    )
+                })
+                .and_then(|_| tx.commit())
+                .map_err(|err| DatabaseError::Migration(target, err))?;
+
+            self.is_dirty = true;
+        }
 
-    fn try_read_last_modified<P: AsRef<Path>>(db_file: P) -> Option<DateTime<Utc>> {
-        let mut datetime = None;
+        Ok(())
+    }
 
-        if let Ok(conn) = Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_ONLY) {
-            let db = Self {
-                connection: conn,
-                is_dirty: false,
-            };
-            datetime = db.try_get_last_modified();
+    fn with_connection(connection: Connection) -> Self {
+        Self {
+            connection,
+            is_dirty: false,
+            unsaved_changes: 0,
+            undo_stack: Vec::new(),
+            transaction_depth: 0,
+            transaction_changed: false,
+            last_sync: None,
         }
-
-        datetime
     }
 
-    pub fn _get_used_assets(&self, cfg: &Config) -> Vec<String> {
+    pub fn _get_used_assets(&self, cfg: &Config) -> Result<Vec<String>, DatabaseError> {
         let mut assets = Vec::new();
 
-        let contents = self.read::<String, _>("SELECT content FROM cards", []);
-        for entry in std::fs::read_dir(cfg.get_assets_dir()).unwrap() {
+        let contents = self.read::<String, _>("SELECT content FROM cards", [])?;
+        for entry in std::fs::read_dir(cfg.get_assets_dir())? {
             if let Ok(asset_file) = entry {
                 let file_name = asset_file.file_name();
                 let name_lossy = file_name.to_string_lossy();
@@ -133,57 +628,74 @@ impl Database {
             }
         }
 
-        assets
+        Ok(assets)
     }
 
-    pub fn get_card(&self, id: Id) -> Card {
+    pub fn get_card(&self, id: Id) -> Result<Card, DatabaseError> {
         assert!(id != 0);
-        self.read_single("SELECT * FROM cards WHERE card_id = ?", [id])
-            .unwrap()
+        self.read_single("SELECT * FROM cards WHERE card_id = ?", [id])?
+            .ok_or(DatabaseError::NotFound)
     }
 
-    pub fn get_cards(&self) -> Vec<Card> {
-        self.read("SELECT * FROM cards", [])
+    pub fn search_cards(
+        &self,
+        query: &Query,
+        filter: &TagFilter,
+    ) -> Result<Vec<Card>, DatabaseError> {
+        let (sql, params) = matching_cards_sql(query, filter, "cards.*", None, None);
+        self.read(&sql, params_from_iter(params))
     }
 
-    pub fn get_cards_with_tags(&self, tags: &[Id]) -> Vec<Card> {
-        if tags.is_empty() {
-            return self.get_cards();
-        }
-
-        self.read(
-            &format!(
-                r#"
-                SELECT * FROM cards
-                JOIN card_tag USING (card_id)
-                WHERE tag_id IN ({})
-                GROUP BY card_id
-                HAVING Count(*) = {}
-                "#,
-                tags.iter().map(|_| "?").collect::<Vec<_>>().join(","),
-                tags.len()
-            ),
-            params_from_iter(tags.iter()),
-        )
+    pub fn search_card_summaries(
+        &self,
+        query: &Query,
+        filter: &TagFilter,
+        sort: Option<CardSort>,
+        page: Page,
+    ) -> Result<Vec<CardSummary>, DatabaseError> {
+        let snippet = match query.has_text_terms() {
+            true => "snippet(cards_fts, 0, char(2), char(3), '…', 16)",
+            false => "NULL",
+        };
+        // Only the start of the content is loaded, enough for a one line preview.
+        let columns = format!(
+            r#"
+            cards.card_id,
+            substr(cards.content, 1, 200),
+            {snippet},
+            {CARD_TAG_NAMES} AS tag_names,
+            cards.due_date,
+            cards.due_days,
+            cards.recall_attempts,
+            cards.successful_recalls,
+            cards.note_id,
+            cards.suspended,
+            cards.created_at,
+            cards.modified_at
+            "#
+        );
+        let (sql, params) = matching_cards_sql(query, filter, &columns, sort, Some(page));
+        self.read(&sql, params_from_iter(params))
     }
 
-    pub fn get_cards_without_tags(&self) -> Vec<Card> {
-        self.read(
-            r#"
-                SELECT * FROM cards c WHERE NOT EXISTS (
-                    SELECT ct.card_id FROM card_tag ct
-                    WHERE c.card_id = ct.card_id
-                )
-                "#,
-            [],
-        )
+    pub fn count_matching_cards(
+        &self,
+        query: &Query,
+        filter: &TagFilter,
+    ) -> Result<usize, DatabaseError> {
+        let (sql, params) = matching_cards_sql(query, filter, "cards.card_id", None, None);
+        self.read_single(
+            &format!("SELECT COUNT(*) FROM ({sql})"),
+            params_from_iter(params),
+        )?
+        .ok_or(DatabaseError::NotFound)
     }
 
-    pub fn _get_due_card_random(&self) -> Option<Card> {
+    pub fn _get_due_card_random(&self) -> Result<Option<Card>, DatabaseError> {
         self.read_single(
             r#"
             SELECT * FROM cards
-            WHERE due_date <= (date('now'))
+            WHERE due_date <= (date('now')) AND suspended = 0 AND deleted_at IS NULL
             ORDER BY RANDOM()
             LIMIT 1
             "#,
@@ -191,42 +703,103 @@ impl Database {
         )
     }
 
-    pub fn get_due_cards(&self) -> Vec<Card> {
+    pub fn get_due_cards(&self) -> Result<Vec<Card>, DatabaseError> {
         self.read(
             r#"
             SELECT * FROM cards
-            WHERE due_date <= (date('now'))
+            WHERE due_date <= (date('now')) AND suspended = 0 AND deleted_at IS NULL
             ORDER BY due_date ASC
             "#,
             [],
         )
     }
 
-    pub fn _get_due_cards_count(&self) -> usize {
+    pub fn get_due_cards_matching(&self, query: &Query) -> Result<Vec<Card>, DatabaseError> {
+        let mut query = query.clone();
+        query.clauses.push(Clause {
+            negated: false,
+            term: Term::Is(CardState::Due),
+        });
+
+        let mut cards = self.search_cards(&query, &TagFilter::default())?;
+        cards.sort_by_key(|card| card.review.due_date);
+        Ok(cards)
+    }
+
+    pub fn _get_due_cards_count(&self) -> Result<usize, DatabaseError> {
         self.read_single(
             r#"
             SELECT COUNT(card_id) FROM cards
-            WHERE due_date <= (date('now'))
+            WHERE due_date <= (date('now')) AND suspended = 0 AND deleted_at IS NULL
             "#,
             [],
-        )
-        .unwrap()
+        )?
+        .ok_or(DatabaseError::NotFound)
     }
 
-    pub fn create_card(&mut self, content: &str) -> Id {
-        self.write("INSERT INTO cards (content) VALUES (?)", [content]);
-        self.last_insert_rowid()
+    pub fn create_card(&mut self, content: &str) -> Result<Id, DatabaseError> {
+        self.write(
+            r#"
+            INSERT INTO cards (content, created_at, modified_at)
+            VALUES (?, datetime('now'), datetime('now'))
+            "#,
+            [content],
+        )?;
+        Ok(self.last_insert_rowid())
     }
 
-    pub fn update_card_content(&mut self, id: Id, content: &str) {
+    pub fn update_card_content(&mut self, id: Id, content: &str) -> Result<(), DatabaseError> {
         assert!(id != 0);
-        self.write(
-            "UPDATE cards SET content = ? WHERE card_id = ?",
-            params![content, id],
-        );
+        self.write_transaction(|tx| {
+            tx.execute(
+                r#"
+                INSERT INTO card_revisions (card_id, content)
+                SELECT card_id, content FROM cards
+                WHERE card_id = ? AND content != ?
+                "#,
+                params![id, content],
+            )?;
+            tx.execute(
+                r#"
+                UPDATE cards SET content = ?1, modified_at = datetime('now')
+                WHERE card_id = ?2 AND content != ?1
+                "#,
+                params![content, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn get_card_revision(&self, id: Id) -> Result<CardRevision, DatabaseError> {
+        assert!(id != 0);
+        self.read_single("SELECT * FROM card_revisions WHERE revision_id = ?", [id])?
+            .ok_or(DatabaseError::NotFound)
+    }
+
+    // Newest first.
+    pub fn get_card_revisions(&self, card: Id) -> Result<Vec<CardRevision>, DatabaseError> {
+        assert!(card != 0);
+        self.read(
+            r#"
+            SELECT * FROM card_revisions
+            WHERE card_id = ?
+            ORDER BY created_at DESC, revision_id DESC
+            "#,
+            [card],
+        )
     }
 
-    pub fn update_card_review(&mut self, id: Id, review: CardReview) {
+    // The current content is kept as a revision, so restoring can be undone.
+    // Note cards can't be restored, their note would render them again.
+    pub fn restore_card_revision(&mut self, id: Id) -> Result<(), DatabaseError> {
+        let revision = self.get_card_revision(id)?;
+        if self.get_card(revision.card)?.note.is_some() {
+            return Err(DatabaseError::NoteCardRevision);
+        }
+        self.update_card_content(revision.card, &revision.content)
+    }
+
+    pub fn update_card_review(&mut self, id: Id, review: CardReview) -> Result<(), DatabaseError> {
         assert!(id != 0);
         self.write(
             r#"
@@ -241,158 +814,1234 @@ impl Database {
                 review.successful_recalls,
                 id
             ],
-        );
+        )?;
+        Ok(())
     }
 
-    pub fn _delete_card(&mut self, id: Id) {
-        self.write("DELETE FROM cards WHERE card_id = ?", [id]);
+    // Moves the card to the trash, keeping its tags and review state.
+    pub fn delete_card(&mut self, id: Id) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        self.write(
+            "UPDATE cards SET deleted_at = (datetime('now')) WHERE card_id = ?",
+            [id],
+        )?;
+        Ok(())
     }
 
-    pub fn _get_tag(&self, id: Id) -> Tag {
+    pub fn restore_card(&mut self, id: Id) -> Result<(), DatabaseError> {
         assert!(id != 0);
-        self.read_single("SELECT * FROM tags WHERE tag_id = ?", [id])
-            .unwrap()
+        self.write("UPDATE cards SET deleted_at = NULL WHERE card_id = ?", [id])?;
+        Ok(())
     }
 
-    pub fn get_tags(&self) -> Vec<Tag> {
-        self.read("SELECT * FROM tags", [])
+    pub fn get_deleted_cards(&self) -> Result<Vec<Card>, DatabaseError> {
+        self.read(
+            r#"
+            SELECT * FROM cards
+            WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, card_id DESC
+            "#,
+            [],
+        )
     }
 
-    pub fn _create_tag(&mut self, name: &str) -> Id {
-        self.write("INSERT INTO tags (name) VALUES (?)", [name]);
-        self.last_insert_rowid()
+    pub fn purge_card(&mut self, id: Id) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        self.purge_cards("card_id = ? AND deleted_at IS NOT NULL", [id])
     }
 
-    pub fn _update_tag_name(&mut self, id: Id, name: &str) {
-        assert!(id != 0);
-        self.write(
-            "UPDATE tags SET name = ? WHERE tag_id = ?",
-            params![name, id],
-        );
+    pub fn empty_trash(&mut self) -> Result<(), DatabaseError> {
+        self.purge_cards("deleted_at IS NOT NULL", [])
     }
 
-    pub fn _delete_tag(&mut self, id: Id) {
-        self.write("DELETE FROM tags WHERE tag_id = ?", [id]);
+    pub fn purge_trash_older_than(&mut self, days: usize) -> Result<(), DatabaseError> {
+        self.purge_cards("deleted_at <= datetime('now', '-' || ? || ' days')", [days])
     }
 
-    pub fn save(&self, cfg: &Config) {
-        if !self.is_dirty {
-            return;
+    // Permanently removes the trashed cards matching `condition`, along with
+    // notes that no longer have any cards.
+    fn purge_cards<P: Params + Copy>(
+        &mut self,
+        condition: &str,
+        params: P,
+    ) -> Result<(), DatabaseError> {
+        let count = self
+            .read_single::<usize, _>(
+                &format!("SELECT COUNT(card_id) FROM cards WHERE {condition}"),
+                params,
+            )?
+            .unwrap_or(0);
+        if count == 0 {
+            return Ok(());
         }
 
-        if let Some(location) = cfg.get_location() {
-            if let Ok(file) = File::create(location) {
-                let mut writer = ZipWriter::new(file);
-
-                // Write database file
-                writer.write_file(cfg.get_db_file(), cfg.get_db_file_name());
-
-                // Write asset files
-                for asset_file_name in &self._get_used_assets(cfg) {
-                    let file_path = cfg.get_assets_dir().join(asset_file_name);
-                    let zip_name = format!("{}/{}", cfg.get_assets_dir_name(), asset_file_name);
-                    writer.write_file(file_path, &zip_name)
-                }
+        self.write_transaction(|tx| {
+            for table in ["card_tag", "card_revisions", "card_conflicts"] {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE card_id IN (SELECT card_id FROM cards WHERE {condition})"
+                    ),
+                    params,
+                )?;
             }
-        }
+            tx.execute(&format!("DELETE FROM cards WHERE {condition}"), params)?;
+            tx.execute_batch(
+                r#"
+                DELETE FROM note_values WHERE note_id NOT IN (
+                    SELECT note_id FROM cards WHERE note_id IS NOT NULL
+                );
+                DELETE FROM notes WHERE note_id NOT IN (
+                    SELECT note_id FROM cards WHERE note_id IS NOT NULL
+                );
+                "#,
+            )
+        })
     }
 
-    fn last_insert_rowid(&self) -> Id {
-        let id = self.connection.last_insert_rowid();
-        id.try_into().unwrap()
+    pub fn get_note_types(&self) -> Result<Vec<NoteType>, DatabaseError> {
+        self.read("SELECT * FROM note_types", [])
     }
 
-    fn try_get_version(&self) -> Option<usize> {
-        let mut version = None;
+    pub fn get_note_fields(&self, note_type: Id) -> Result<Vec<NoteField>, DatabaseError> {
+        assert!(note_type != 0);
+        self.read(
+            r#"
+            SELECT field_id, name FROM note_fields
+            WHERE note_type_id = ?
+            ORDER BY ordinal ASC
+            "#,
+            [note_type],
+        )
+    }
 
-        self.read_single_with(
-            "SELECT metadata_id, version FROM metadata WHERE metadata_id = 1",
-            [],
-            |row| {
-                version = Some(row.get(1).unwrap());
-            },
-        );
+    pub fn get_note_templates(&self, note_type: Id) -> Result<Vec<NoteTemplate>, DatabaseError> {
+        assert!(note_type != 0);
+        self.read(
+            r#"
+            SELECT template_id, name, content FROM note_templates
+            WHERE note_type_id = ?
+            ORDER BY ordinal ASC
+            "#,
+            [note_type],
+        )
+    }
 
-        version
+    pub fn get_note(&self, id: Id) -> Result<Note, DatabaseError> {
+        assert!(id != 0);
+        let note_type = self
+            .read_single("SELECT note_type_id FROM notes WHERE note_id = ?", [id])?
+            .ok_or(DatabaseError::NotFound)?;
+        let values = self.read(
+            r#"
+            SELECT IFNULL(v.value, '') FROM note_fields f
+            LEFT JOIN note_values v ON v.field_id = f.field_id AND v.note_id = ?
+            WHERE f.note_type_id = ?
+            ORDER BY f.ordinal ASC
+            "#,
+            params![id, note_type],
+        )?;
+
+        Ok(Note {
+            id,
+            note_type,
+            values,
+        })
     }
 
-    fn _set_version(&mut self, version: usize) {
-        self.write(
-            "UPDATE metadata SET version = ? WHERE metadata_id = 1",
-            params![version],
-        );
+    pub fn get_cards_of_note(&self, note: Id) -> Result<Vec<Card>, DatabaseError> {
+        assert!(note != 0);
+        self.read("SELECT * FROM cards WHERE note_id = ?", [note])
     }
 
-    fn try_get_last_modified(&self) -> Option<DateTime<Utc>> {
-        let mut datetime = None;
+    pub fn create_note(&mut self, note_type: Id, values: &[String]) -> Result<Id, DatabaseError> {
+        assert!(note_type != 0);
+        self.transaction(|db| {
+            db.write("INSERT INTO notes (note_type_id) VALUES (?)", [note_type])?;
+            let id = db.last_insert_rowid();
+            db.update_note(id, values)?;
+            Ok(id)
+        })
+    }
 
-        self.read_single_with(
-            "SELECT metadata_id, last_modified FROM metadata WHERE metadata_id = 1",
-            [],
-            |row| {
-                datetime = Some(row.get(1).unwrap());
-            },
-        );
+    pub fn update_note(&mut self, id: Id, values: &[String]) -> Result<(), DatabaseError> {
+        self.transaction(|db| {
+            let note = db.get_note(id)?;
+            let fields = db.get_note_fields(note.note_type)?;
 
-        datetime
+            for (field, value) in fields.iter().zip(values) {
+                db.write(
+                    "INSERT OR REPLACE INTO note_values (note_id, field_id, value) VALUES (?, ?, ?)",
+                    params![id, field.id, value],
+                )?;
+            }
+
+            db.render_note_cards(id)
+        })
     }
 
-    fn _get_last_modified(&self) -> DateTime<Utc> {
-        self.try_get_last_modified().unwrap()
+    // The contents of the cards a note with `values` has, with the ids of
+    // their templates. Contents can be empty.
+    pub fn render_note(
+        &self,
+        note_type: Id,
+        values: &[String],
+    ) -> Result<Vec<(Id, String)>, DatabaseError> {
+        let fields = self.get_note_fields(note_type)?;
+        let named_values = fields
+            .iter()
+            .zip(values)
+            .map(|(field, value)| (field.name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .get_note_templates(note_type)?
+            .into_iter()
+            .map(|t| (t.id, template::render(&t.content, &named_values)))
+            .collect())
     }
 
-    fn update_last_modified(&self) {
-        self.connection
-            .execute(
-                "UPDATE metadata SET last_modified = (datetime('now')) WHERE metadata_id = 1",
+    fn render_note_cards(&mut self, id: Id) -> Result<(), DatabaseError> {
+        let note = self.get_note(id)?;
+
+        for (template, content) in self.render_note(note.note_type, &note.values)? {
+            let card = self.read_single::<Id, _>(
+                "SELECT card_id FROM cards WHERE note_id = ? AND template_id = ?",
+                [id, template],
+            )?;
+
+            match card {
+                Some(card) => {
+                    self.update_card_content(card, &content)?;
+                }
+                None if !content.trim().is_empty() => {
+                    self.write(
+                        r#"
+                        INSERT INTO cards (content, note_id, template_id, created_at, modified_at)
+                        VALUES (?, ?, ?, datetime('now'), datetime('now'))
+                        "#,
+                        params![content, id, template],
+                    )?;
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // Deletes the note and turns its cards into plain cards with the content
+    // they have.
+    fn drop_note(&mut self, id: Id) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        self.write_transaction(|tx| {
+            tx.execute(
+                "UPDATE cards SET note_id = NULL, template_id = NULL WHERE note_id = ?",
+                [id],
+            )?;
+            tx.execute("DELETE FROM note_values WHERE note_id = ?", [id])?;
+            tx.execute("DELETE FROM notes WHERE note_id = ?", [id])?;
+            Ok(())
+        })
+    }
+
+    // Most similar first.
+    pub fn find_similar_cards(&self, content: &str) -> Result<Vec<SimilarCard>, DatabaseError> {
+        let content = duplicates::normalize(content);
+
+        let mut similar = self
+            .get_contents()?
+            .into_iter()
+            .map(|(id, other)| {
+                let other = duplicates::normalize(&other);
+                (id, duplicates::similarity(&content, &other))
+            })
+            .filter(|(_, similarity)| *similarity >= duplicates::SIMILARITY_THRESHOLD)
+            .map(|(id, similarity)| {
+                Ok(SimilarCard {
+                    card: self.get_card(id)?,
+                    similarity,
+                })
+            })
+            .collect::<Result<Vec<_>, DatabaseError>>()?;
+        similar.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        Ok(similar)
+    }
+
+    // Keeps one card of a group of duplicates with the tags of all of them and
+    // moves the others to the trash.
+    pub fn merge_duplicate_cards(&mut self, keep: Id, others: &[Id]) -> Result<(), DatabaseError> {
+        assert!(keep != 0);
+        self.write_transaction(|tx| {
+            for other in others.iter().filter(|other| **other != keep) {
+                tx.execute(
+                    r#"
+                    INSERT OR IGNORE INTO card_tag (card_id, tag_id)
+                    SELECT ?, tag_id FROM card_tag WHERE card_id = ?
+                    "#,
+                    [keep, *other],
+                )?;
+                tx.execute(
+                    "UPDATE cards SET deleted_at = (datetime('now')) WHERE card_id = ?",
+                    [other],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn search_card_ids(
+        &self,
+        query: &Query,
+        filter: &TagFilter,
+    ) -> Result<Vec<Id>, DatabaseError> {
+        let (sql, params) = matching_cards_sql(query, filter, "cards.card_id", None, None);
+        self.read(&sql, params_from_iter(params))
+    }
+
+    pub fn apply_bulk_action(
+        &mut self,
+        cards: &[Id],
+        action: &BulkAction,
+    ) -> Result<(), DatabaseError> {
+        if cards.is_empty() {
+            return Ok(());
+        }
+
+        let entry = self.transaction(|db| {
+            // The selection can be the whole collection, so each step is a
+            // single statement over this table.
+            db.connection
+                .execute_batch("CREATE TEMP TABLE bulk_cards (card_id INTEGER PRIMARY KEY)")?;
+            let mut insert = db
+                .connection
+                .prepare("INSERT OR IGNORE INTO bulk_cards (card_id) VALUES (?)")?;
+            for card in cards {
+                insert.execute([card])?;
+            }
+            drop(insert);
+
+            let before = db.read_bulk_columns(action.columns())?;
+            let tagged = match action {
+                BulkAction::AddTag(name) | BulkAction::RemoveTag(name) => db.read(
+                    &format!(
+                        r#"
+                        SELECT card_tag.card_id FROM card_tag
+                        JOIN tags ON tags.tag_id = card_tag.tag_id
+                        WHERE tags.name = ? AND card_tag.{IN_BULK_CARDS}
+                        "#
+                    ),
+                    [name],
+                )?,
+                _ => Vec::new(),
+            };
+
+            if let BulkAction::AddTag(name) = action {
+                for name in tag_tree::ancestors(name).into_iter().chain([name.as_str()]) {
+                    db.connection
+                        .execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", [name])?;
+                }
+            }
+
+            let (sql, params) = match action {
+                BulkAction::AddTag(name) => (
+                    format!(
+                        r#"
+                        INSERT OR IGNORE INTO card_tag (card_id, tag_id)
+                        SELECT cards.card_id, tags.tag_id FROM cards, tags
+                        WHERE tags.name = ? AND cards.{IN_BULK_CARDS}
+                        "#
+                    ),
+                    vec![Value::Text(name.clone())],
+                ),
+                BulkAction::RemoveTag(name) => (
+                    format!(
+                        r#"
+                        DELETE FROM card_tag
+                        WHERE tag_id IN (SELECT tag_id FROM tags WHERE name = ?) AND {IN_BULK_CARDS}
+                        "#
+                    ),
+                    vec![Value::Text(name.clone())],
+                ),
+                BulkAction::Reschedule(days) => (
+                    format!(
+                        r#"
+                        UPDATE cards SET due_date = date('now', '+' || ? || ' days')
+                        WHERE {IN_BULK_CARDS}
+                        "#
+                    ),
+                    vec![Value::Integer(*days as i64)],
+                ),
+                BulkAction::ResetProgress => (
+                    format!(
+                        r#"
+                        UPDATE cards
+                        SET due_date = date('now'), due_days = 0, recall_attempts = 0, successful_recalls = 0
+                        WHERE {IN_BULK_CARDS}
+                        "#
+                    ),
+                    Vec::new(),
+                ),
+                BulkAction::Suspend => (
+                    format!("UPDATE cards SET suspended = 1 WHERE {IN_BULK_CARDS}"),
+                    Vec::new(),
+                ),
+                BulkAction::Unsuspend => (
+                    format!("UPDATE cards SET suspended = 0 WHERE {IN_BULK_CARDS}"),
+                    Vec::new(),
+                ),
+                BulkAction::Delete => (
+                    format!("UPDATE cards SET deleted_at = (datetime('now')) WHERE {IN_BULK_CARDS}"),
+                    Vec::new(),
+                ),
+            };
+            db.connection.execute(&sql, params_from_iter(params))?;
+
+            let after = db.read_bulk_columns(action.columns())?;
+            db.connection.execute_batch("DROP TABLE bulk_cards")?;
+            db.mark_changed()?;
+
+            // Both list the same cards, since none are removed in between.
+            Ok(UndoEntry {
+                description: action.to_string(),
+                action: action.clone(),
+                cards: before
+                    .into_iter()
+                    .zip(after)
+                    .map(|((card, before), (_, after))| (card, before, after))
+                    .collect(),
+                tagged,
+            })
+        })?;
+
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(entry);
+        Ok(())
+    }
+
+    pub fn get_undo_description(&self) -> Option<&str> {
+        self.undo_stack
+            .last()
+            .map(|entry| entry.description.as_str())
+    }
+
+    // Reverts the last bulk action. Cards edited since keep the edit: only the
+    // columns the action wrote are restored, and only where they still have
+    // the values it gave them. Tags created by the action are kept.
+    pub fn undo(&mut self) -> Result<(), DatabaseError> {
+        let entry = match self.undo_stack.pop() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        let result = self.write_transaction(|tx| {
+            let columns = entry.action.columns();
+            if !columns.is_empty() {
+                let assignments = columns
+                    .iter()
+                    .map(|column| format!("{column} = ?"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let unchanged = columns
+                    .iter()
+                    .map(|column| format!("{column} IS ?"))
+                    .collect::<Vec<_>>()
+                    .join(" AND ");
+                let mut statement = tx.prepare(&format!(
+                    "UPDATE cards SET {assignments} WHERE card_id = ? AND {unchanged}"
+                ))?;
+                for (card, before, after) in &entry.cards {
+                    let card = Value::Integer(*card as i64);
+                    statement.execute(params_from_iter(
+                        before.iter().chain([&card]).chain(after),
+                    ))?;
+                }
+            }
+
+            match &entry.action {
+                BulkAction::AddTag(name) => {
+                    for (card, _, _) in &entry.cards {
+                        if !entry.tagged.contains(card) {
+                            tx.execute(
+                                r#"
+                                DELETE FROM card_tag
+                                WHERE card_id = ? AND tag_id IN (SELECT tag_id FROM tags WHERE name = ?)
+                                "#,
+                                params![card, name],
+                            )?;
+                        }
+                    }
+                }
+                // Skips cards and tags that were deleted in the meantime.
+                BulkAction::RemoveTag(name) => {
+                    for card in &entry.tagged {
+                        tx.execute(
+                            r#"
+                            INSERT OR IGNORE INTO card_tag (card_id, tag_id)
+                            SELECT cards.card_id, tags.tag_id FROM cards, tags
+                            WHERE cards.card_id = ? AND tags.name = ?
+                            "#,
+                            params![card, name],
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        });
+
+        // Keeps the entry so that a failed undo can be retried.
+        if result.is_err() {
+            self.undo_stack.push(entry);
+        }
+        result
+    }
+
+    pub fn get_tag(&self, id: Id) -> Result<Tag, DatabaseError> {
+        assert!(id != 0);
+        self.read_single("SELECT * FROM tags WHERE tag_id = ?", [id])?
+            .ok_or(DatabaseError::NotFound)
+    }
+
+    pub fn get_tags(&self) -> Result<Vec<Tag>, DatabaseError> {
+        self.read("SELECT * FROM tags", [])
+    }
+
+    pub fn get_tag_counts(&self) -> Result<Vec<TagCount>, DatabaseError> {
+        self.read(
+            r#"
+            SELECT tags.tag_id, tags.name, tags.changed_at, tags.guid, Count(cards.card_id)
+            FROM tags
+            LEFT JOIN card_tag USING (tag_id)
+            LEFT JOIN cards ON cards.card_id = card_tag.card_id AND cards.deleted_at IS NULL
+            GROUP BY tag_id
+            ORDER BY name ASC
+            "#,
+            [],
+        )
+    }
+
+    pub fn get_tag_by_name(&self, name: &str) -> Result<Option<Tag>, DatabaseError> {
+        self.read_single("SELECT * FROM tags WHERE name = ?", [name])
+    }
+
+    pub fn get_tags_for_card(&self, card: Id) -> Result<Vec<Tag>, DatabaseError> {
+        assert!(card != 0);
+        self.read(
+            r#"
+            SELECT tags.* FROM tags
+            JOIN card_tag USING (tag_id)
+            WHERE card_id = ?
+            ORDER BY name ASC
+            "#,
+            [card],
+        )
+    }
+
+    pub fn get_tag_descendants(&self, id: Id) -> Result<Vec<Tag>, DatabaseError> {
+        assert!(id != 0);
+        self.read(
+            r#"
+            SELECT t.* FROM tags t
+            JOIN tags p ON p.tag_id = ?
+            WHERE substr(t.name, 1, length(p.name) + 2) = p.name || '::'
+            ORDER BY t.name ASC
+            "#,
+            [id],
+        )
+    }
+
+    pub fn create_tag(&mut self, name: &str) -> Result<Id, DatabaseError> {
+        self.transaction(|db| {
+            for ancestor in tag_tree::ancestors(name) {
+                db.get_or_create_tag(ancestor)?;
+            }
+
+            db.write("INSERT INTO tags (name) VALUES (?)", [name])?;
+            Ok(db.last_insert_rowid())
+        })
+    }
+
+    pub fn get_or_create_tag(&mut self, name: &str) -> Result<Id, DatabaseError> {
+        match self.get_tag_by_name(name)? {
+            Some(tag) => Ok(tag.id),
+            None => self.create_tag(name),
+        }
+    }
+
+    pub fn add_tag_to_card(&mut self, card: Id, tag: Id) -> Result<(), DatabaseError> {
+        assert!(card != 0 && tag != 0);
+        self.write(
+            "INSERT OR IGNORE INTO card_tag (card_id, tag_id) VALUES (?, ?)",
+            [card, tag],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_tag_from_card(&mut self, card: Id, tag: Id) -> Result<(), DatabaseError> {
+        assert!(card != 0 && tag != 0);
+        self.write(
+            "DELETE FROM card_tag WHERE card_id = ? AND tag_id = ?",
+            [card, tag],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_card_tags(&mut self, card: Id, names: &[String]) -> Result<(), DatabaseError> {
+        self.transaction(|db| {
+            let current = db.get_tags_for_card(card)?;
+
+            for tag in &current {
+                if !names.contains(&tag.name) {
+                    db.remove_tag_from_card(card, tag.id)?;
+                }
+            }
+
+            for name in names {
+                if !current.iter().any(|tag| &tag.name == name) {
+                    let tag = db.get_or_create_tag(name)?;
+                    db.add_tag_to_card(card, tag)?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    // Gives every card of the note the same tags.
+    pub fn set_note_tags(&mut self, note: Id, names: &[String]) -> Result<(), DatabaseError> {
+        self.transaction(|db| {
+            for card in db.get_cards_of_note(note)? {
+                db.set_card_tags(card.id, names)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn is_tag_rename_conflicting(&self, id: Id, name: &str) -> Result<bool, DatabaseError> {
+        let tag = self.get_tag(id)?;
+        let descendants = self.get_tag_descendants(id)?;
+
+        for name in std::iter::once(name.to_owned()).chain(
+            descendants
+                .iter()
+                .map(|t| format!("{}{}", name, &t.name[tag.name.len()..])),
+        ) {
+            if let Some(other) = self.get_tag_by_name(&name)? {
+                if other.id != id && !descendants.iter().any(|t| t.id == other.id) {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Renames the whole subtree, so `A::B` becomes `C::B` when `A` is renamed to `C`.
+    pub fn update_tag_name(&mut self, id: Id, name: &str) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        self.transaction(|db| {
+            let tag = db.get_tag(id)?;
+
+            db.write(
+                r#"
+                UPDATE tags SET name = ?1 || substr(name, length(?2) + 1)
+                WHERE tag_id = ?3 OR substr(name, 1, length(?2) + 2) = ?2 || '::'
+                "#,
+                params![name, tag.name, id],
+            )?;
+
+            for ancestor in tag_tree::ancestors(name) {
+                db.get_or_create_tag(ancestor)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    // Descendants move along, merging with their counterparts under the target if they exist.
+    pub fn merge_tags(&mut self, from: Id, into: Id) -> Result<(), DatabaseError> {
+        assert!(from != 0 && into != 0 && from != into);
+        let from_tag = self.get_tag(from)?;
+        let into_tag = self.get_tag(into)?;
+        assert!(!tag_tree::is_descendant(&into_tag.name, &from_tag.name));
+
+        let moves = std::iter::once((from, into_tag.name.clone()))
+            .chain(self.get_tag_descendants(from)?.into_iter().map(|t| {
+                let name = format!("{}{}", into_tag.name, &t.name[from_tag.name.len()..]);
+                (t.id, name)
+            }))
+            .map(|(id, name)| Ok((id, self.get_tag_by_name(&name)?.map(|t| t.id), name)))
+            .collect::<Result<Vec<_>, DatabaseError>>()?;
+
+        self.write_transaction(|tx| {
+            for (id, target, name) in &moves {
+                match target {
+                    Some(target) => {
+                        tx.execute(
+                            r#"
+                            INSERT OR IGNORE INTO card_tag (card_id, tag_id)
+                            SELECT card_id, ? FROM card_tag WHERE tag_id = ?
+                            "#,
+                            [target, id],
+                        )?;
+                        tx.execute("DELETE FROM card_tag WHERE tag_id = ?", [id])?;
+                        tx.execute("DELETE FROM tags WHERE tag_id = ?", [id])?;
+                    }
+                    None => {
+                        tx.execute(
+                            "UPDATE tags SET name = ? WHERE tag_id = ?",
+                            params![name, id],
+                        )?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Deletes the tag together with all of its descendants.
+    pub fn delete_tag(&mut self, id: Id) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        let ids = std::iter::once(id)
+            .chain(self.get_tag_descendants(id)?.iter().map(|t| t.id))
+            .collect::<Vec<_>>();
+
+        self.write_transaction(|tx| {
+            for id in &ids {
+                tx.execute("DELETE FROM card_tag WHERE tag_id = ?", [id])?;
+                tx.execute("DELETE FROM tags WHERE tag_id = ?", [id])?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn get_saved_search(&self, id: Id) -> Result<SavedSearch, DatabaseError> {
+        assert!(id != 0);
+        self.read_single(
+            "SELECT * FROM saved_searches WHERE saved_search_id = ?",
+            [id],
+        )?
+        .ok_or(DatabaseError::NotFound)
+    }
+
+    pub fn get_saved_searches(&self) -> Result<Vec<SavedSearch>, DatabaseError> {
+        self.read("SELECT * FROM saved_searches ORDER BY name ASC", [])
+    }
+
+    pub fn get_saved_search_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<SavedSearch>, DatabaseError> {
+        self.read_single("SELECT * FROM saved_searches WHERE name = ?", [name])
+    }
+
+    // Saving under an existing name replaces that search's query.
+    pub fn save_search(&mut self, name: &str, query: &str) -> Result<Id, DatabaseError> {
+        match self.get_saved_search_by_name(name)? {
+            Some(saved) => {
+                self.write(
+                    "UPDATE saved_searches SET query = ? WHERE saved_search_id = ?",
+                    params![query, saved.id],
+                )?;
+                Ok(saved.id)
+            }
+            None => {
+                self.write(
+                    "INSERT INTO saved_searches (name, query) VALUES (?, ?)",
+                    [name, query],
+                )?;
+                Ok(self.last_insert_rowid())
+            }
+        }
+    }
+
+    pub fn delete_saved_search(&mut self, id: Id) -> Result<(), DatabaseError> {
+        assert!(id != 0);
+        self.write("DELETE FROM saved_searches WHERE saved_search_id = ?", [id])?;
+        Ok(())
+    }
+
+    // Merges the changes made in other copies into this one. With a sync
+    // server set they are exchanged with the server, otherwise the collection
+    // saved at the location is merged in and what this one adds to it is
+    // written there with the next save.
+    pub fn sync(&mut self, cfg: &Config) -> Result<Option<&SyncReport>, DatabaseError> {
+        if let Some(sync) = self.start_server_sync(cfg)? {
+            let exchange = sync.exchange()?;
+            return self.finish_server_sync(cfg, exchange).map(Some);
+        }
+        let location = match cfg.get_location() {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let file = match File::open(location) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut reader = ZipArchive::new(file).map_err(ArchiveError::from)?;
+        let other_db_file = match Self::extract_other(cfg, &mut reader)? {
+            Some(other_db_file) => other_db_file,
+            None => return Ok(None),
+        };
+
+        let report = self.with_other(other_db_file.path(), |db| {
+            let report = sync::sync(&db.connection, "other")?;
+            // The saved collection is behind until this one is written there.
+            if !report.sent.is_empty() {
+                db.mark_changed()?;
+            }
+            Ok(report)
+        })?;
+
+        self.last_sync = Some(report);
+        Ok(self.last_sync.as_ref())
+    }
+
+    // Takes the changes made here since the last sync with the server, or
+    // returns None without a sync server. A failed sync leaves this copy as it
+    // was.
+    pub fn start_server_sync(&self, cfg: &Config) -> Result<Option<ServerSync>, DatabaseError> {
+        let url = match cfg.get_sync_server() {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let client = SyncClient::new(url, cfg.get_sync_user(), cfg.get_sync_password())?;
+        let server = format!("{}@{url}", cfg.get_sync_user());
+
+        let (last_server, mut pulled, mut pushed_at) = self
+            .read_single_with(
+                r#"
+                SELECT sync_server, sync_pulled, sync_pushed_at FROM metadata
+                WHERE metadata_id = 1
+                "#,
                 [],
-            )
-            .unwrap();
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get(1)?, row.get(2)?)),
+            )?
+            .ok_or(DatabaseError::NotFound)?;
+        if last_server.as_deref() != Some(server.as_str()) {
+            pulled = 0;
+            pushed_at = String::new();
+        }
+
+        // Taken before pulling, so that what arrives isn't sent back.
+        let now: String = self.connection.query_row(
+            "SELECT strftime('%Y-%m-%d %H:%M:%f', 'now')",
+            [],
+            |row| row.get(0),
+        )?;
+        let (changes, sent) = self.export_changes(cfg, &pushed_at)?;
+
+        Ok(Some(ServerSync {
+            client,
+            server,
+            pulled,
+            pushed_at,
+            now,
+            changes,
+            sent,
+            app_dir: cfg.get_app_dir().to_owned(),
+        }))
+    }
+
+    // Merges in the changes the exchange pulled. Changes made here in the
+    // meantime go with the next sync.
+    pub fn finish_server_sync(
+        &mut self,
+        cfg: &Config,
+        exchange: ServerExchange,
+    ) -> Result<&SyncReport, DatabaseError> {
+        let ServerSync {
+            server,
+            mut pulled,
+            pushed_at,
+            now,
+            sent,
+            ..
+        } = exchange.sync;
+
+        let mut report = SyncReport::default();
+        for (seq, file) in exchange.pulled {
+            let mut reader = ZipArchive::new(file.reopen()?).map_err(ArchiveError::from)?;
+            if let Some(other_db_file) = Self::extract_other(cfg, &mut reader)? {
+                report.received += self.with_other(other_db_file.path(), |db| {
+                    let received = sync::receive(&db.connection, "other")?;
+                    if !received.is_empty() {
+                        db.mark_changed()?;
+                    }
+                    Ok(received)
+                })?;
+            }
+            pulled = seq;
+            self.set_sync_state(&server, pulled, &pushed_at)?;
+        }
+
+        // Unless another copy pushed in between, there is nothing left to
+        // pull before this copy's own changes.
+        if exchange.pushed == Some(pulled + 1) {
+            pulled += 1;
+        }
+        self.set_sync_state(&server, pulled, &now)?;
+
+        report.sent = sent;
+        Ok(self.last_sync.insert(report))
+    }
+
+    pub fn get_last_sync(&self) -> Option<&SyncReport> {
+        self.last_sync.as_ref()
+    }
+
+    pub fn get_card_conflicts(&self) -> Result<Vec<CardConflict>, DatabaseError> {
+        self.read("SELECT * FROM card_conflicts ORDER BY card_id ASC", [])
+    }
+
+    // Settles a conflict with `content`, which can be either version or a
+    // merge of both. A version that isn't kept stays in the card's history.
+    // A note card that gets other content is no longer its note's, as with
+    // sync.
+    pub fn resolve_card_conflict(&mut self, card: Id, content: &str) -> Result<(), DatabaseError> {
+        assert!(card != 0);
+        self.transaction(|db| {
+            let conflict: CardConflict = db
+                .read_single("SELECT * FROM card_conflicts WHERE card_id = ?", [card])?
+                .ok_or(DatabaseError::NotFound)?;
+
+            let current = db.get_card(card)?;
+            if let Some(note) = current.note.filter(|_| current.content != content) {
+                db.drop_note(note)?;
+            }
+
+            if conflict.content != content {
+                db.write(
+                    "INSERT INTO card_revisions (card_id, content) VALUES (?, ?)",
+                    params![card, conflict.content],
+                )?;
+            }
+            db.update_card_content(card, content)?;
+
+            // The result is based on both versions, so it replaces the other
+            // copy's with the next sync.
+            let ancestors = sync::capped_ancestors(
+                "ancestors || changed_at || ' ' || (
+                    SELECT ancestors || changed_at FROM card_conflicts WHERE card_id = ?1
+                ) || ' '",
+            );
+            db.write(
+                &format!(
+                    r#"
+                    UPDATE cards SET
+                        changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+                        ancestors = {ancestors}
+                    WHERE card_id = ?1
+                    "#
+                ),
+                [card],
+            )?;
+            db.write("DELETE FROM card_conflicts WHERE card_id = ?", [card])?;
+            Ok(())
+        })
+    }
+
+    pub fn get_unsaved_changes(&self) -> usize {
+        self.unsaved_changes
+    }
+
+    pub fn save(&mut self, cfg: &Config) -> Result<(), DatabaseError> {
+        if !self.is_dirty {
+            return Ok(());
+        }
+
+        if let Some(location) = cfg.get_location() {
+            let used_assets = self._get_used_assets(cfg)?;
+
+            write_archive(location, |writer| {
+                write_collection(writer, cfg, &cfg.get_db_file(), &used_assets)
+            })?;
+
+            self.is_dirty = false;
+            self.unsaved_changes = 0;
+        }
+
+        backup::backup_if_due(cfg)
+    }
+
+    // Replaces the collection with a backup, after backing up the current one.
+    // The restored collection replaces the other copies' on the next sync too.
+    pub fn restore_backup(&mut self, cfg: &Config, backup: &Path) -> Result<(), DatabaseError> {
+        let mut reader = ZipArchive::new(File::open(backup)?).map_err(ArchiveError::from)?;
+        let db_bytes = reader.read_file(cfg.get_db_file_name())?;
+        let mut temp_db_file = tempfile::NamedTempFile::new_in(cfg.get_app_dir())?;
+        temp_db_file.write_all(&db_bytes)?;
+
+        // Check the backup before anything is replaced.
+        let version =
+            Connection::open_with_flags(temp_db_file.path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?
+                .query_row(
+                    "SELECT version FROM metadata WHERE metadata_id = 1",
+                    [],
+                    |row| row.get(0),
+                )?;
+        if version > DATABASE_VERSION {
+            return Err(DatabaseError::UnsupportedVersion(version));
+        }
+
+        backup::create_backup(cfg)?;
+        let replaced = tempfile::NamedTempFile::new_in(cfg.get_app_dir())?;
+        self.connection
+            .execute("VACUUM INTO ?", [replaced.path().to_string_lossy()])?;
+
+        let connection = std::mem::replace(&mut self.connection, Connection::open_in_memory()?);
+        if let Err((connection, err)) = connection.close() {
+            self.connection = connection;
+            return Err(err.into());
+        }
+        let persisted = temp_db_file.persist(cfg.get_db_file());
+        self.connection = open_connection(&cfg.get_db_file())?;
+        persisted.map_err(|err| err.error)?;
+
+        self.undo_stack.clear();
+        reader.extract_dir(cfg.get_assets_dir_name(), cfg.get_assets_dir(), true)?;
+        self.migrate(version, None)?;
+        self.with_other(replaced.path(), |db| {
+            Ok(sync::supersede(&db.connection, "other")?)
+        })?;
+
+        // The restored collection must count as newer than the saved archive,
+        // which would replace it on the next start otherwise.
+        self.mark_changed()
     }
 
-    fn read_single_with<P, F>(&self, sql: &str, params: P, f: F)
+    // Runs `f` atomically: its writes are committed together if it returns
+    // `Ok` and rolled back otherwise. Transactions can be nested.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, DatabaseError>
     where
-        P: Params,
-        F: FnOnce(&Row),
+        F: FnOnce(&mut Self) -> Result<T, DatabaseError>,
     {
+        let savepoint = format!("transaction_{}", self.transaction_depth);
         self.connection
-            .query_row(sql, params, |row| Ok(f(row)))
-            .ok();
+            .execute_batch(&format!("SAVEPOINT {savepoint}"))?;
+        self.transaction_depth += 1;
+        let undo_len = self.undo_stack.len();
+
+        let result = f(self).and_then(|value| {
+            if self.transaction_depth == 1 && self.transaction_changed {
+                self.update_last_modified()?;
+            }
+            Ok(value)
+        });
+        self.transaction_depth -= 1;
+
+        // Releasing the outermost savepoint commits the transaction.
+        let result = result.and_then(|value| {
+            self.connection
+                .execute_batch(&format!("RELEASE {savepoint}"))?;
+            Ok(value)
+        });
+        if result.is_err() {
+            self.connection
+                .execute_batch(&format!("ROLLBACK TO {savepoint}; RELEASE {savepoint}"))
+                .ok();
+            // Bulk actions that were rolled back can't be undone.
+            self.undo_stack.truncate(undo_len);
+        }
+        if self.transaction_depth == 0 {
+            if result.is_ok() && self.transaction_changed {
+                self.unsaved_changes += 1;
+            }
+            self.transaction_changed = false;
+        }
+
+        result
+    }
+
+    fn last_insert_rowid(&self) -> Id {
+        let id = self.connection.last_insert_rowid();
+        id.try_into().unwrap()
+    }
+
+    // Extracts the database of a collection archive to a temporary file and
+    // brings it up to this app's version, along with the assets this copy
+    // doesn't have yet.
+    fn extract_other(
+        cfg: &Config,
+        reader: &mut ZipArchive<File>,
+    ) -> Result<Option<tempfile::NamedTempFile>, DatabaseError> {
+        let other_db_bytes = reader.read_file(cfg.get_db_file_name())?;
+        let mut other_db_file = tempfile::NamedTempFile::new_in(cfg.get_app_dir())?;
+        other_db_file.write_all(&other_db_bytes)?;
+
+        let mut other = Self::with_connection(open_connection(other_db_file.path())?);
+        match other.try_get_version() {
+            Some(version) => other.migrate(version, None)?,
+            None => return Ok(None),
+        }
+        drop(other);
+
+        reader.extract_dir(cfg.get_assets_dir_name(), cfg.get_assets_dir(), false)?;
+        Ok(Some(other_db_file))
     }
 
-    fn read_with<P, F>(&self, sql: &str, params: P, mut f: F)
+    // Runs `f` in a transaction with the database in `file` attached as
+    // `other`.
+    fn with_other<T, F>(&mut self, file: &Path, f: F) -> Result<T, DatabaseError>
     where
-        P: Params,
-        F: FnMut(&Row),
+        F: FnOnce(&mut Self) -> Result<T, DatabaseError>,
     {
-        match self.connection.prepare(sql) {
-            Ok(mut stmt) => match stmt.query(params) {
-                Ok(mut rows) => {
-                    while let Ok(Some(row)) = rows.next() {
-                        f(row);
-                    }
-                }
-                Err(err) => panic!("{err}"),
+        self.connection
+            .execute("ATTACH DATABASE ? AS other", [file.to_string_lossy()])?;
+        let result = self.transaction(f);
+        let detached = self.connection.execute("DETACH DATABASE other", []);
+        let value = result?;
+        detached?;
+        Ok(value)
+    }
+
+    // The changes made after `since` as a collection archive, and what they
+    // contain.
+    fn export_changes(
+        &self,
+        cfg: &Config,
+        since: &str,
+    ) -> Result<(Vec<u8>, SyncChanges), DatabaseError> {
+        let dir = tempfile::tempdir_in(cfg.get_app_dir())?;
+        let db_file = dir.path().join(cfg.get_db_file_name());
+        self.connection
+            .execute("VACUUM INTO ?", [db_file.to_string_lossy()])?;
+
+        let changes = Self::with_connection(open_connection(&db_file)?);
+        let contained = sync::keep_changes_since(&changes.connection, since)?;
+        changes.connection.execute_batch("VACUUM")?;
+        let used_assets = changes._get_used_assets(cfg)?;
+        drop(changes);
+
+        let archive = dir.path().join(CHANGES_FILE_NAME);
+        write_archive(&archive, |writer| {
+            write_collection(writer, cfg, &db_file, &used_assets)
+        })?;
+        Ok((std::fs::read(archive)?, contained))
+    }
+
+    fn set_sync_state(
+        &self,
+        server: &str,
+        pulled: u64,
+        pushed_at: &str,
+    ) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            r#"
+            UPDATE metadata SET sync_server = ?, sync_pulled = ?, sync_pushed_at = ?
+            WHERE metadata_id = 1
+            "#,
+            params![server, pulled, pushed_at],
+        )?;
+        Ok(())
+    }
+
+    // A database without a metadata table is new.
+    fn try_get_version(&self) -> Option<usize> {
+        self.read_single_with(
+            "SELECT metadata_id, version FROM metadata WHERE metadata_id = 1",
+            [],
+            |row| row.get(1),
+        )
+        .ok()
+        .flatten()
+    }
+
+    fn try_get_last_modified(&self) -> Option<DateTime<Utc>> {
+        self.read_single_with(
+            "SELECT metadata_id, last_modified FROM metadata WHERE metadata_id = 1",
+            [],
+            |row| row.get(1),
+        )
+        .ok()
+        .flatten()
+    }
+
+    fn _get_last_modified(&self) -> Result<DateTime<Utc>, DatabaseError> {
+        self.try_get_last_modified().ok_or(DatabaseError::NotFound)
+    }
+
+    fn get_contents(&self) -> Result<Vec<(Id, String)>, DatabaseError> {
+        let mut contents = Vec::new();
+        self.read_with(
+            "SELECT card_id, content FROM cards WHERE deleted_at IS NULL",
+            [],
+            |row| {
+                contents.push((row.get(0)?, row.get(1)?));
+                Ok(())
             },
-            Err(err) => panic!("{err}"),
-        };
+        )?;
+        Ok(contents)
     }
 
-    fn read_single<T, P>(&self, sql: &str, params: P) -> Option<T>
+    // The values of `columns` for each of the cards in `bulk_cards` that
+    // exist, in the order of their ids.
+    fn read_bulk_columns(&self, columns: &[&str]) -> Result<Vec<(Id, Vec<Value>)>, DatabaseError> {
+        let sql = format!(
+            "SELECT card_id{} FROM cards WHERE {IN_BULK_CARDS} ORDER BY card_id",
+            columns
+                .iter()
+                .map(|column| format!(", {column}"))
+                .collect::<String>()
+        );
+        let mut values = Vec::new();
+        self.read_with(&sql, [], |row| {
+            let card_values = (1..=columns.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            values.push((row.get(0)?, card_values));
+            Ok(())
+        })?;
+        Ok(values)
+    }
+
+    fn update_last_modified(&self) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            "UPDATE metadata SET last_modified = (datetime('now')) WHERE metadata_id = 1",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn read_single_with<T, P, F>(
+        &self,
+        sql: &str,
+        params: P,
+        f: F,
+    ) -> Result<Option<T>, DatabaseError>
     where
-        T: FromRow,
         P: Params,
+        F: FnOnce(&Row) -> rusqlite::Result<T>,
     {
-        let mut item = None;
+        Ok(self.connection.query_row(sql, params, f).optional()?)
+    }
 
-        self.read_single_with(sql, params, |row| {
-            item = Some(T::from_row(row));
-        });
+    fn read_with<P, F>(&self, sql: &str, params: P, mut f: F) -> Result<(), DatabaseError>
+    where
+        P: Params,
+        F: FnMut(&Row) -> rusqlite::Result<()>,
+    {
+        let mut stmt = self.connection.prepare(sql)?;
+        let mut rows = stmt.query(params)?;
+        while let Some(row) = rows.next()? {
+            f(row)?;
+        }
+        Ok(())
+    }
 
-        item
+    fn read_single<T, P>(&self, sql: &str, params: P) -> Result<Option<T>, DatabaseError>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        self.read_single_with(sql, params, T::from_row)
     }
 
-    fn read<T, P>(&self, sql: &str, params: P) -> Vec<T>
+    fn read<T, P>(&self, sql: &str, params: P) -> Result<Vec<T>, DatabaseError>
     where
         T: FromRow,
         P: Params,
@@ -400,71 +2049,835 @@ impl Database {
         let mut items = Vec::new();
 
         self.read_with(sql, params, |row| {
-            items.push(T::from_row(row));
-        });
+            items.push(T::from_row(row)?);
+            Ok(())
+        })?;
 
-        items
+        Ok(items)
     }
 
-    fn write<P>(&mut self, sql: &str, params: P) -> usize
+    fn write<P>(&mut self, sql: &str, params: P) -> Result<usize, DatabaseError>
     where
         P: Params,
     {
-        let changed_rows = match self.connection.execute(sql, params) {
-            Ok(changed_rows) => changed_rows,
-            Err(err) => panic!("{err}"),
-        };
+        let changed_rows = self.connection.execute(sql, params)?;
+        self.mark_changed()?;
+        Ok(changed_rows)
+    }
 
-        self.update_last_modified();
-        self.is_dirty = true;
+    fn write_transaction<F>(&mut self, f: F) -> Result<(), DatabaseError>
+    where
+        F: FnOnce(&Connection) -> rusqlite::Result<()>,
+    {
+        self.transaction(|db| {
+            f(&db.connection)?;
+            db.mark_changed()
+        })
+    }
 
-        changed_rows
+    // Outside of a transaction every write updates `last_modified`, inside
+    // one it is updated once when the transaction commits.
+    fn mark_changed(&mut self) -> Result<(), DatabaseError> {
+        self.is_dirty = true;
+        match self.transaction_depth {
+            0 => {
+                self.unsaved_changes += 1;
+                self.update_last_modified()
+            }
+            _ => {
+                self.transaction_changed = true;
+                Ok(())
+            }
+        }
     }
 
-    fn write_batch(&mut self, sql: &str) {
-        self.connection.execute_batch(sql).unwrap();
+    fn write_batch(&mut self, sql: &str) -> Result<(), DatabaseError> {
+        self.connection.execute_batch(sql)?;
         self.is_dirty = true;
+        Ok(())
     }
 }
 
+// Opens the database in `path` with the functions the app's queries use.
+fn open_connection(path: &Path) -> Result<Connection, DatabaseError> {
+    let connection = Connection::open(path)?;
+    search::add_functions(&connection)?;
+    Ok(connection)
+}
+
+// Writes the database in `db_file` and the named assets in the layout of a
+// collection archive.
+fn write_collection(
+    writer: &mut ZipWriter<File>,
+    cfg: &Config,
+    db_file: &Path,
+    assets: &[String],
+) -> Result<(), ArchiveError> {
+    // Write database file
+    writer.write_file(db_file, cfg.get_db_file_name())?;
+
+    // Write asset files
+    for asset_file_name in assets {
+        let file_path = cfg.get_assets_dir().join(asset_file_name);
+        let zip_name = format!("{}/{}", cfg.get_assets_dir_name(), asset_file_name);
+        writer.write_file(file_path, &zip_name)?;
+    }
+
+    Ok(())
+}
+
+fn tag_filter_condition(filter: &TagFilter) -> (String, Vec<Id>) {
+    let mut all = vec!["1".to_owned()];
+    let mut all_params = Vec::new();
+    let mut any = Vec::new();
+    let mut any_params = Vec::new();
+
+    // Selecting a tag includes every card tagged with one of its descendants.
+    for &(id, state) in &filter.tags {
+        match state {
+            TagFilterState::Include => {
+                all.push(HAS_TAG_OR_DESCENDANT.to_owned());
+                all_params.push(id);
+            }
+            TagFilterState::Exclude => {
+                all.push(format!("NOT {HAS_TAG_OR_DESCENDANT}"));
+                all_params.push(id);
+            }
+            TagFilterState::AnyOf => {
+                any.push(HAS_TAG_OR_DESCENDANT);
+                any_params.push(id);
+            }
+        }
+    }
+
+    if !any.is_empty() {
+        all.push(format!("({})", any.join(" OR ")));
+        all_params.append(&mut any_params);
+    }
+
+    if filter.tagless {
+        all.push(
+            r#"
+            NOT EXISTS (
+                SELECT 1 FROM card_tag ct
+                WHERE ct.card_id = cards.card_id
+            )
+            "#
+            .to_owned(),
+        );
+    }
+
+    (all.join(" AND "), all_params)
+}
+
+// Builds the query behind card searches. Text searches select from the FTS
+// table so that `rank` and `snippet()` are available to `columns` and `sort`.
+fn matching_cards_sql(
+    query: &Query,
+    filter: &TagFilter,
+    columns: &str,
+    sort: Option<CardSort>,
+    page: Option<Page>,
+) -> (String, Vec<Value>) {
+    let compiled = query.compile();
+    let (tag_condition, tag_params) = tag_filter_condition(filter);
+
+    let mut params = compiled.params;
+    params.extend(tag_params.into_iter().map(|id| Value::Integer(id as i64)));
+
+    let order = match (sort, &compiled.fts_match) {
+        (Some(sort), _) => sort.to_sql(),
+        (None, Some(_)) => "rank".to_owned(),
+        (None, None) => "cards.card_id ASC".to_owned(),
+    };
+
+    let mut sql = match compiled.fts_match {
+        Some(fts_match) => {
+            params.insert(0, Value::Text(fts_match));
+            format!(
+                r#"
+                SELECT {columns}
+                FROM cards_fts
+                JOIN cards ON cards.card_id = cards_fts.rowid
+                WHERE cards_fts MATCH ? AND cards.deleted_at IS NULL AND {} AND {tag_condition}
+                ORDER BY {order}
+                "#,
+                compiled.condition
+            )
+        }
+        None => format!(
+            r#"
+            SELECT {columns}
+            FROM cards
+            WHERE cards.deleted_at IS NULL AND {} AND {tag_condition}
+            ORDER BY {order}
+            "#,
+            compiled.condition
+        ),
+    };
+
+    if let Some(page) = page {
+        sql.push_str("LIMIT ? OFFSET ?");
+        params.push(Value::Integer(page.limit as i64));
+        params.push(Value::Integer(page.offset as i64));
+    }
+
+    (sql, params)
+}
+
+// The cards a bulk action applies to.
+const IN_BULK_CARDS: &str = "card_id IN (SELECT card_id FROM bulk_cards)";
+
+// Tag names are joined with the unit separator, which can't be typed into a tag.
+const CARD_TAG_NAMES: &str = r#"(
+    SELECT group_concat(name, char(31)) FROM (
+        SELECT t.name FROM card_tag ct
+        JOIN tags t ON t.tag_id = ct.tag_id
+        WHERE ct.card_id = cards.card_id
+        ORDER BY t.name
+    )
+)"#;
+
+const HAS_TAG_OR_DESCENDANT: &str = r#"
+    EXISTS (
+        SELECT 1 FROM card_tag ct
+        JOIN tags t ON t.tag_id = ct.tag_id
+        JOIN tags p ON p.tag_id = ?
+        WHERE ct.card_id = cards.card_id
+        AND (t.tag_id = p.tag_id OR substr(t.name, 1, length(p.name) + 2) = p.name || '::')
+    )
+"#;
+
 impl FromRow for Card {
-    fn from_row(row: &Row) -> Self {
-        Self {
-            id: row.get(0).unwrap(),
-            content: row.get(1).unwrap(),
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            guid: row.get(12)?,
+            content: row.get(1)?,
             review: CardReview {
-                due_date: row.get(2).unwrap(),
-                due_days: row.get(3).unwrap(),
-                recall_attempts: row.get(4).unwrap(),
-                successful_recalls: row.get(5).unwrap(),
+                due_date: row.get(2)?,
+                due_days: row.get(3)?,
+                recall_attempts: row.get(4)?,
+                successful_recalls: row.get(5)?,
             },
-        }
+            note: row.get(6)?,
+            suspended: row.get(8)?,
+            deleted_at: row.get(9)?,
+            created_at: row.get(10)?,
+            modified_at: row.get(11)?,
+        })
+    }
+}
+
+impl FromRow for CardConflict {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            card: row.get(0)?,
+            content: row.get(1)?,
+            changed_at: row.get(2)?,
+        })
+    }
+}
+
+impl FromRow for CardRevision {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            card: row.get(1)?,
+            content: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    }
+}
+
+impl FromRow for SavedSearch {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            query: row.get(2)?,
+        })
+    }
+}
+
+impl FromRow for CardSummary {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            preview: row.get(1)?,
+            snippet: row.get(2)?,
+            tags: row
+                .get::<_, Option<String>>(3)?
+                .map_or(Vec::new(), |names| {
+                    names.split('\u{1f}').map(str::to_owned).collect()
+                }),
+            review: CardReview {
+                due_date: row.get(4)?,
+                due_days: row.get(5)?,
+                recall_attempts: row.get(6)?,
+                successful_recalls: row.get(7)?,
+            },
+            note: row.get(8)?,
+            suspended: row.get(9)?,
+            created_at: row.get(10)?,
+            modified_at: row.get(11)?,
+        })
     }
 }
 
 impl FromRow for Tag {
-    fn from_row(row: &Row) -> Self {
-        Self {
-            id: row.get(0).unwrap(),
-            name: row.get(1).unwrap(),
-        }
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            guid: row.get(3)?,
+            name: row.get(1)?,
+        })
+    }
+}
+
+impl FromRow for TagCount {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            tag: Tag::from_row(row)?,
+            card_count: row.get(4)?,
+        })
+    }
+}
+
+impl FromRow for NoteType {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    }
+}
+
+impl FromRow for NoteField {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    }
+}
+
+impl FromRow for NoteTemplate {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            content: row.get(2)?,
+        })
     }
 }
 
 impl FromRow for String {
-    fn from_row(row: &Row) -> Self {
-        row.get(0).unwrap()
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        row.get(0)
     }
 }
 
 impl FromRow for usize {
-    fn from_row(row: &Row) -> Self {
-        row.get(0).unwrap()
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        row.get(0)
     }
 }
 
 impl FromRow for DateTime<Utc> {
-    fn from_row(row: &Row) -> Self {
-        row.get(0).unwrap()
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        row.get(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A copy of the collection saved at `location`.
+    fn open_copy(dir: &Path, location: &Path) -> (Config, Database) {
+        let mut cfg = Config::in_dir(dir);
+        cfg.set_location(location);
+        let db = Database::new(&cfg).unwrap();
+        (cfg, db)
+    }
+
+    // A database of the first version with the cards `contents`, upgraded to
+    // this app's version.
+    fn upgraded(contents: &[&str]) -> Database {
+        let mut db = Database::with_connection(open_connection(Path::new(":memory:")).unwrap());
+        db.write_batch(include_str!("schema.sql")).unwrap();
+        db.write_batch("DELETE FROM card_tag; DELETE FROM cards")
+            .unwrap();
+        for content in contents {
+            db.write("INSERT INTO cards (content) VALUES (?)", [content])
+                .unwrap();
+        }
+        db.migrate(1, None).unwrap();
+        db
+    }
+
+    fn tag_names(db: &Database, card: Id) -> Vec<String> {
+        db.get_tags_for_card(card)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect()
+    }
+
+    fn contents(db: &Database) -> Vec<String> {
+        let mut contents = db
+            .search_cards(&Query::default(), &TagFilter::default())
+            .unwrap()
+            .into_iter()
+            .map(|card| card.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    #[test]
+    fn splits_old_cards_into_notes_only_at_separator_lines() {
+        let db = upgraded(&["a --- b\n\n---\n\nc\n---\nd", "| a |\n|---|", "front\n---"]);
+        let values = (1..=3)
+            .map(|note| db.get_note(note).unwrap().values)
+            .collect::<Vec<_>>();
+        assert_eq!(values[0], ["a --- b", "c\n---\nd"]);
+        assert_eq!(values[1], ["| a |\n|---|", ""]);
+        assert_eq!(values[2], ["front", ""]);
+    }
+
+    #[test]
+    fn renames_and_merges_tags_with_their_descendants() {
+        let mut db = upgraded(&["one", "two"]);
+        db.set_card_tags(1, &["A::B".to_owned()]).unwrap();
+        db.set_card_tags(2, &["A".to_owned(), "C::B".to_owned()])
+            .unwrap();
+
+        let a = db.get_tag_by_name("A").unwrap().unwrap().id;
+        let c = db.get_tag_by_name("C").unwrap().unwrap().id;
+        assert!(db.is_tag_rename_conflicting(a, "C").unwrap());
+        db.update_tag_name(a, "D").unwrap();
+        assert_eq!(tag_names(&db, 1), ["D::B"]);
+        assert_eq!(tag_names(&db, 2), ["C::B", "D"]);
+
+        db.merge_tags(a, c).unwrap();
+        assert_eq!(tag_names(&db, 1), ["C::B"]);
+        assert_eq!(tag_names(&db, 2), ["C", "C::B"]);
+        assert!(db.get_tag_by_name("D").unwrap().is_none());
+        assert!(db.get_tag_by_name("D::B").unwrap().is_none());
+    }
+
+    #[test]
+    fn combines_tag_filters() {
+        let mut db = upgraded(&["basics", "advanced", "go", "untagged"]);
+        db.set_card_tags(1, &["Rust".to_owned()]).unwrap();
+        db.set_card_tags(2, &["Rust::Advanced".to_owned()]).unwrap();
+        db.set_card_tags(3, &["Go".to_owned()]).unwrap();
+        let tag = |name| db.get_tag_by_name(name).unwrap().unwrap().id;
+        let (rust, advanced, go) = (tag("Rust"), tag("Rust::Advanced"), tag("Go"));
+        let matching = |tags: &[(Id, TagFilterState)], tagless| {
+            let filter = TagFilter {
+                tags: tags.to_vec(),
+                tagless,
+            };
+            db.search_card_ids(&Query::default(), &filter).unwrap()
+        };
+
+        use TagFilterState::*;
+        assert_eq!(matching(&[(rust, Include)], false), [1, 2]);
+        assert_eq!(
+            matching(&[(rust, Include), (advanced, Exclude)], false),
+            [1]
+        );
+        assert_eq!(matching(&[(advanced, AnyOf), (go, AnyOf)], false), [2, 3]);
+        assert_eq!(matching(&[(rust, Exclude), (go, AnyOf)], false), [3]);
+        assert_eq!(matching(&[], true), [4]);
+        assert_eq!(matching(&[(go, Exclude)], true), [4]);
+    }
+
+    #[test]
+    fn undoes_bulk_actions_on_the_cards_they_changed() {
+        let mut db = upgraded(&["one", "two", "three"]);
+        db.set_card_tags(1, &["Done".to_owned()]).unwrap();
+        let done = BulkAction::AddTag("Done".to_owned());
+        db.apply_bulk_action(&[1, 2, 99], &done).unwrap();
+        db.apply_bulk_action(&[2, 3], &BulkAction::Delete).unwrap();
+        assert_eq!(tag_names(&db, 2), ["Done"]);
+        assert_eq!(contents(&db), ["one"]);
+
+        // Trashed again since, so undoing leaves it alone.
+        db.write(
+            "UPDATE cards SET deleted_at = '2000-01-01 00:00:00' WHERE card_id = 3",
+            [],
+        )
+        .unwrap();
+        assert_eq!(db.get_undo_description(), Some("Move to trash"));
+        db.undo().unwrap();
+        assert_eq!(contents(&db), ["one", "two"]);
+
+        db.undo().unwrap();
+        assert_eq!(tag_names(&db, 1), ["Done"]);
+        assert!(tag_names(&db, 2).is_empty());
+        assert_eq!(db.get_undo_description(), None);
+    }
+
+    #[test]
+    fn purges_cards_trashed_longer_than_the_retention() {
+        let mut db = upgraded(&["old", "recent", "kept"]);
+        db.set_card_tags(2, &["Tag".to_owned()]).unwrap();
+        db.delete_card(1).unwrap();
+        db.delete_card(2).unwrap();
+        db.write(
+            "UPDATE cards SET deleted_at = datetime('now', '-31 days') WHERE card_id = 1",
+            [],
+        )
+        .unwrap();
+
+        db.purge_trash_older_than(30).unwrap();
+        assert!(matches!(db.get_card(1), Err(DatabaseError::NotFound)));
+        let trashed = db.get_deleted_cards().unwrap();
+        assert_eq!(trashed.iter().map(|card| card.id).collect::<Vec<_>>(), [2]);
+        assert_eq!(contents(&db), ["kept"]);
+
+        db.restore_card(2).unwrap();
+        assert_eq!(contents(&db), ["kept", "recent"]);
+        assert_eq!(tag_names(&db, 2), ["Tag"]);
+    }
+
+    #[test]
+    fn restores_revisions_of_plain_cards_only() {
+        let mut db = upgraded(&[]);
+        let card = db.create_card("one").unwrap();
+        db.update_card_content(card, "two").unwrap();
+        let revision = db.get_card_revisions(card).unwrap()[0].id;
+        db.restore_card_revision(revision).unwrap();
+        assert_eq!(db.get_card(card).unwrap().content, "one");
+        assert_eq!(db.get_card_revisions(card).unwrap().len(), 2);
+
+        let values = ["front".to_owned(), "back".to_owned()];
+        let note = db.create_note(1, &values).unwrap();
+        db.update_note(note, &["other".to_owned(), "back".to_owned()])
+            .unwrap();
+        let card = db.get_cards_of_note(note).unwrap()[0].id;
+        let revision = db.get_card_revisions(card).unwrap()[0].id;
+        assert!(matches!(
+            db.restore_card_revision(revision),
+            Err(DatabaseError::NoteCardRevision)
+        ));
+    }
+
+    #[test]
+    fn upgrades_old_databases_after_backing_them_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("collection.db.v1.bak");
+        let mut db =
+            Database::with_connection(open_connection(&dir.path().join("collection.db")).unwrap());
+        db.write_batch(include_str!("schema.sql")).unwrap();
+
+        db.migrate(1, Some(&backup)).unwrap();
+        assert_eq!(db.try_get_version(), Some(DATABASE_VERSION));
+        assert_eq!(tag_names(&db, 2), ["Tag2", "Tag3"]);
+        assert_eq!(db.get_note(2).unwrap().values, ["front", "back"]);
+        let version: usize = Connection::open(&backup)
+            .unwrap()
+            .query_row("SELECT version FROM metadata", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 1);
+
+        let newer = DATABASE_VERSION + 1;
+        assert!(matches!(
+            db.migrate(newer, None),
+            Err(DatabaseError::UnsupportedVersion(version)) if version == newer
+        ));
+    }
+
+    #[test]
+    fn rolls_back_failed_transactions() {
+        let mut db = upgraded(&["one"]);
+        let unsaved = db.get_unsaved_changes();
+
+        let result = db.transaction(|db| {
+            db.create_card("two")?;
+            db.transaction(|db| db.update_card_content(1, "changed"))?;
+            db.write("INSERT INTO missing DEFAULT VALUES", [])
+        });
+        assert!(matches!(result, Err(DatabaseError::Sql(_))));
+        assert_eq!(contents(&db), ["one"]);
+        assert!(db.get_card_revisions(1).unwrap().is_empty());
+        assert_eq!(db.get_unsaved_changes(), unsaved);
+
+        db.transaction(|db| {
+            db.create_card("two")?;
+            db.create_card("three")
+        })
+        .unwrap();
+        assert_eq!(contents(&db), ["one", "three", "two"]);
+        assert_eq!(db.get_unsaved_changes(), unsaved + 1);
+    }
+
+    #[test]
+    fn searches_tags_by_name_in_any_case() {
+        let mut db = upgraded(&["one", "two", "three"]);
+        db.set_card_tags(1, &["Über::Straße".to_owned()]).unwrap();
+        db.set_card_tags(2, &["Книги".to_owned()]).unwrap();
+        db.set_card_tags(3, &["Ubung".to_owned()]).unwrap();
+        let matching = |query| {
+            db.search_card_ids(&Query::parse(query).unwrap(), &TagFilter::default())
+                .unwrap()
+        };
+
+        assert_eq!(matching("tag:Über"), [1]);
+        assert_eq!(matching("tag:über::STRASSE"), Vec::<Id>::new());
+        assert_eq!(matching("tag:ÜBER::straße"), [1]);
+        assert_eq!(matching("tag:книги"), [2]);
+        assert_eq!(matching("-tag:КНИГИ"), [1, 3]);
+    }
+
+    #[test]
+    fn restored_backup_wins_the_next_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        let card = db_a.create_card("before").unwrap();
+        db_a.save(&cfg_a).unwrap();
+        let backup = backup::create_backup(&cfg_a).unwrap();
+        let restored = contents(&db_a);
+
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        db_b.update_card_content(card, "after").unwrap();
+        db_b.create_card("added").unwrap();
+        db_b.save(&cfg_b).unwrap();
+        db_a.sync(&cfg_a).unwrap();
+        assert_ne!(contents(&db_a), restored);
+
+        db_a.restore_backup(&cfg_a, &backup).unwrap();
+        db_a.sync(&cfg_a).unwrap();
+        assert_eq!(contents(&db_a), restored);
+        db_a.save(&cfg_a).unwrap();
+
+        let report = db_b.sync(&cfg_b).unwrap().unwrap();
+        assert_eq!(report.received.cards_updated, 1);
+        assert_eq!(report.received.cards_deleted, 1);
+        assert_eq!(contents(&db_b), restored);
+        assert!(db_b.get_card_conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn note_cards_changed_in_the_other_copy_become_plain_cards() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        let note_type = db_a.get_note_types().unwrap()[0].id;
+        let fields = db_a.get_note_fields(note_type).unwrap().len();
+        let note = db_a
+            .create_note(note_type, &vec!["one".to_owned(); fields])
+            .unwrap();
+        db_a.save(&cfg_a).unwrap();
+
+        // Copies made from the saved collection share its notes.
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        let cards = db_b.get_cards_of_note(note).unwrap();
+        assert!(!cards.is_empty());
+
+        db_a.update_note(note, &vec!["two".to_owned(); fields])
+            .unwrap();
+        db_a.save(&cfg_a).unwrap();
+        db_b.sync(&cfg_b).unwrap();
+
+        assert!(db_b.get_cards_of_note(note).unwrap().is_empty());
+        assert!(db_b.get_note(note).is_err());
+        for card in cards {
+            let card = db_b.get_card(card.id).unwrap();
+            assert!(card.note.is_none());
+            assert!(card.content.contains("two"));
+        }
+    }
+
+    #[test]
+    fn resolving_a_note_card_with_other_content_drops_the_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        // Vocabulary notes make two cards.
+        let note_type = db_a.get_note_types().unwrap()[1].id;
+        let fields = db_a.get_note_fields(note_type).unwrap().len();
+        let note = db_a
+            .create_note(note_type, &vec!["one".to_owned(); fields])
+            .unwrap();
+        db_a.save(&cfg_a).unwrap();
+
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        db_a.update_note(note, &vec!["two".to_owned(); fields])
+            .unwrap();
+        db_a.save(&cfg_a).unwrap();
+        db_b.update_note(note, &vec!["three".to_owned(); fields])
+            .unwrap();
+        db_b.sync(&cfg_b).unwrap();
+
+        let conflicts = db_b.get_card_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 2);
+
+        // Keeping this copy's version keeps the note.
+        let first = &conflicts[0];
+        let content = db_b.get_card(first.card).unwrap().content;
+        db_b.resolve_card_conflict(first.card, &content).unwrap();
+        assert!(db_b.get_note(note).is_ok());
+
+        let second = &conflicts[1];
+        db_b.resolve_card_conflict(second.card, &second.content)
+            .unwrap();
+        assert_eq!(db_b.get_card(second.card).unwrap().content, second.content);
+        assert!(db_b.get_note(note).is_err());
+        for conflict in &conflicts {
+            assert!(db_b.get_card(conflict.card).unwrap().note.is_none());
+        }
+    }
+
+    #[test]
+    fn gives_upgraded_cards_stamps_with_milliseconds() {
+        let db = upgraded(&["one"]);
+        let (changed_at, reviewed_at): (String, String) = db
+            .connection
+            .query_row(
+                "SELECT changed_at, reviewed_at FROM cards WHERE card_id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(changed_at.len(), "1970-01-01 00:00:00.000".len());
+        assert_eq!(reviewed_at, "1970-01-01 00:00:00.000");
+    }
+
+    #[test]
+    fn only_takes_whole_stamps_for_earlier_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        let card = db_a.create_card("one").unwrap();
+        db_a.save(&cfg_a).unwrap();
+
+        // The stamp of `a`'s version starts the one `b`'s is based on.
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        db_a.write(
+            r#"
+            UPDATE cards SET content = 'two', changed_at = '2020-01-01 10:00:00', ancestors = ''
+            WHERE card_id = ?
+            "#,
+            [card],
+        )
+        .unwrap();
+        db_a.save(&cfg_a).unwrap();
+        db_b.write(
+            r#"
+            UPDATE cards SET content = 'three', changed_at = '2020-01-01 10:00:01.000',
+                ancestors = '2020-01-01 10:00:00.500 '
+            WHERE card_id = ?
+            "#,
+            [card],
+        )
+        .unwrap();
+        db_b.sync(&cfg_b).unwrap();
+
+        let conflicts = db_b.get_card_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].content, "two");
+    }
+
+    #[test]
+    fn makes_new_versions_only_for_changes_sync_carries() {
+        let mut db = upgraded(&["front\n---\nback"]);
+        let version = |db: &Database| -> (String, String) {
+            db.connection
+                .query_row(
+                    "SELECT changed_at, ancestors FROM cards WHERE card_id = 1",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap()
+        };
+        let upgraded = version(&db);
+
+        // As sync detaches the cards of notes it drops.
+        db.write_batch(
+            "UPDATE cards SET note_id = NULL, template_id = NULL, suspended = suspended",
+        )
+        .unwrap();
+        assert_eq!(version(&db), upgraded);
+
+        for i in 0..150 {
+            db.update_card_content(1, &i.to_string()).unwrap();
+        }
+        let (_, ancestors) = version(&db);
+        assert_eq!(ancestors.len(), 100 * "1970-01-01 00:00:00.000 ".len());
+        assert!(!ancestors.contains(&upgraded.0));
+    }
+
+    #[test]
+    fn keeps_the_cards_of_new_collections_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        assert_eq!(contents(&db_a), contents(&db_b));
+
+        db_a.save(&cfg_a).unwrap();
+        db_b.sync(&cfg_b).unwrap();
+        assert_eq!(contents(&db_b).len(), 2 * contents(&db_a).len());
+    }
+
+    #[test]
+    fn matches_cards_of_copies_that_got_guids_on_their_own_by_their_hint() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().join("collection.rustyzip");
+        let (cfg_a, mut db_a) = open_copy(&dir.path().join("a"), &location);
+        let card = db_a.create_card("one").unwrap();
+        // As the upgrade leaves cards that existed before sync.
+        db_a.write_batch("UPDATE cards SET guid_hint = card_id")
+            .unwrap();
+        db_a.save(&cfg_a).unwrap();
+
+        let (cfg_b, mut db_b) = open_copy(&dir.path().join("b"), &location);
+        db_b.write_batch("UPDATE cards SET guid = lower(hex(randomblob(16)))")
+            .unwrap();
+        db_a.update_card_content(card, "two").unwrap();
+        db_a.save(&cfg_a).unwrap();
+        db_b.sync(&cfg_b).unwrap();
+
+        assert_eq!(contents(&db_b), contents(&db_a));
+        let guid = db_a.get_card(card).unwrap().guid;
+        let guid = guid.min(db_b.get_card(card).unwrap().guid);
+        db_b.save(&cfg_b).unwrap();
+        db_a.sync(&cfg_a).unwrap();
+        assert_eq!(db_a.get_card(card).unwrap().guid, guid);
+        assert_eq!(db_b.get_card(card).unwrap().guid, guid);
+        assert_eq!(contents(&db_a), contents(&db_b));
+    }
+
+    #[test]
+    fn sends_changes_made_during_a_server_sync_with_the_next_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("server");
+        rustycard_sync::users::add_user(&data_dir, "ada", "password").unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || rustycard_sync::serve(listener, &data_dir));
+
+        let mut cfg = Config::in_dir(&dir.path().join("a"));
+        cfg.set_sync_server(&address);
+        cfg.set_sync_user("ada");
+        cfg.set_sync_password("password");
+        let mut db = Database::new(&cfg).unwrap();
+        db.sync(&cfg).unwrap();
+
+        db.create_card("before").unwrap();
+        let exchange = db.start_server_sync(&cfg).unwrap().unwrap().exchange();
+        db.create_card("during").unwrap();
+        let report = db.finish_server_sync(&cfg, exchange.unwrap()).unwrap();
+        assert_eq!(report.sent.cards_added, 1);
+
+        let report = db.sync(&cfg).unwrap().unwrap();
+        assert_eq!(report.sent.cards_added, 1);
+        assert!(report.received.is_empty());
     }
 }
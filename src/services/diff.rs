@@ -0,0 +1,81 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Unchanged(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+// Line based diff using the longest common subsequence of both texts.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // lengths[i][j] is the LCS length of old[i..] and new[j..].
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Unchanged(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, DiffLine::*};
+
+    #[test]
+    fn keeps_unchanged_lines() {
+        assert_eq!(
+            diff_lines("one\ntwo", "one\ntwo"),
+            [Unchanged("one"), Unchanged("two")]
+        );
+    }
+
+    #[test]
+    fn marks_added_and_removed_lines() {
+        assert_eq!(
+            diff_lines("one\ntwo\nthree", "one\n2\nthree\nfour"),
+            [
+                Unchanged("one"),
+                Removed("two"),
+                Added("2"),
+                Unchanged("three"),
+                Added("four"),
+            ]
+        );
+        assert_eq!(
+            diff_lines("one\ntwo", "two"),
+            [Removed("one"), Unchanged("two")]
+        );
+    }
+
+    #[test]
+    fn compares_with_an_empty_side() {
+        assert_eq!(diff_lines("", "one\ntwo"), [Added("one"), Added("two")]);
+        assert_eq!(diff_lines("one", ""), [Removed("one")]);
+        assert!(diff_lines("", "").is_empty());
+    }
+}
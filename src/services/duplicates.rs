@@ -0,0 +1,255 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use pulldown_cmark::{Event, Parser};
+
+use super::database::Id;
+
+// Cards at least this similar after normalization are reported as duplicates.
+pub const SIMILARITY_THRESHOLD: f64 = 0.9;
+
+// Grouping only compares texts that have the same minimum hashes of their
+// character trigrams in at least one band (MinHash). With these sizes a pair at
+// the threshold shares a band with a probability above 99%, while unrelated
+// texts, which have few trigrams in common, rarely do.
+const BANDS: usize = 16;
+const BAND_SIZE: usize = 4;
+
+// Keeps only the text of the markdown, lowercased and with whitespace collapsed,
+// so that formatting changes don't hide a duplicate.
+pub fn normalize(content: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(content) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) | Event::Rule => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// Dice coefficient of the character bigrams of two normalized texts. Empty
+// texts, such as cards with only an image, aren't similar to anything.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    Fingerprint::new(a).similarity(&Fingerprint::new(b))
+}
+
+// Groups the cards whose contents are similar to each other. Contents are
+// expected to be normalized.
+pub fn group(contents: &[(Id, String)]) -> Vec<Vec<Id>> {
+    let fingerprints = contents
+        .iter()
+        .filter(|(_, content)| !content.is_empty())
+        .map(|(id, content)| (*id, Fingerprint::new(content)))
+        .collect::<Vec<_>>();
+
+    let mut buckets = HashMap::<u64, Vec<usize>>::new();
+    for (i, (_, fingerprint)) in fingerprints.iter().enumerate() {
+        for key in fingerprint.bucket_keys() {
+            buckets.entry(key).or_default().push(i);
+        }
+    }
+
+    let mut parents = (0..fingerprints.len()).collect::<Vec<_>>();
+    for bucket in buckets.values() {
+        for (k, &i) in bucket.iter().enumerate() {
+            for &j in &bucket[k + 1..] {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                let (first, second) = (&fingerprints[i].1, &fingerprints[j].1);
+                if a != b
+                    && first.may_be_similar(second)
+                    && first.similarity(second) >= SIMILARITY_THRESHOLD
+                {
+                    parents[b] = a;
+                }
+            }
+        }
+    }
+
+    let mut groups = HashMap::<usize, Vec<Id>>::new();
+    for (i, (id, _)) in fingerprints.iter().enumerate() {
+        let root = root(&mut parents, i);
+        groups.entry(root).or_default().push(*id);
+    }
+
+    let mut groups = groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_unstable();
+            group
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    groups
+}
+
+struct Fingerprint<'a> {
+    text: &'a str,
+    len: usize,
+    bigrams: HashMap<(char, char), usize>,
+}
+
+impl<'a> Fingerprint<'a> {
+    fn new(text: &'a str) -> Self {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut bigrams = HashMap::new();
+        for pair in chars.windows(2) {
+            *bigrams.entry((pair[0], pair[1])).or_insert(0) += 1;
+        }
+
+        Self {
+            text,
+            len: chars.len().saturating_sub(1),
+            bigrams,
+        }
+    }
+
+    // The buckets of the text: one for the whole text, so that equal texts
+    // always meet, and one per band of its minimum trigram hashes.
+    fn bucket_keys(&self) -> Vec<u64> {
+        let mut keys = vec![hash(&self.text)];
+        let chars = self.text.chars().collect::<Vec<_>>();
+        if chars.len() < 3 {
+            return keys;
+        }
+
+        let mut minimums = [u64::MAX; BANDS * BAND_SIZE];
+        for trigram in chars.windows(3) {
+            let trigram = hash(trigram);
+            for (i, minimum) in minimums.iter_mut().enumerate() {
+                *minimum = (*minimum).min(mix(trigram, i as u64));
+            }
+        }
+        keys.extend(
+            minimums
+                .chunks(BAND_SIZE)
+                .enumerate()
+                .map(|band| hash(&band)),
+        );
+        keys
+    }
+
+    // The coefficient can't reach the threshold when the bigram counts differ
+    // too much.
+    fn may_be_similar(&self, other: &Self) -> bool {
+        let (shorter, longer) = (self.len.min(other.len), self.len.max(other.len));
+        2.0 * shorter as f64 >= SIMILARITY_THRESHOLD * (shorter + longer) as f64
+    }
+
+    fn similarity(&self, other: &Self) -> f64 {
+        if self.text.is_empty() || other.text.is_empty() {
+            return 0.0;
+        }
+        if self.text == other.text {
+            return 1.0;
+        }
+        if self.len == 0 || other.len == 0 {
+            return 0.0;
+        }
+
+        let common = self
+            .bigrams
+            .iter()
+            .map(|(bigram, count)| (*count).min(*other.bigrams.get(bigram).unwrap_or(&0)))
+            .sum::<usize>();
+
+        2.0 * common as f64 / (self.len + other.len) as f64
+    }
+}
+
+fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The `i`th of the independent hash functions MinHash needs, applied to an
+// already hashed value (the finalizer of SplitMix64).
+fn mix(hash: u64, i: u64) -> u64 {
+    let mut x = hash.wrapping_add((i + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_markdown_to_text() {
+        assert_eq!(
+            normalize("# Hello   **World**\n\n- one\n- `two`"),
+            "hello world one two"
+        );
+        assert_eq!(normalize("![](image.png)\n\n---"), "");
+    }
+
+    #[test]
+    fn compares_bigrams() {
+        assert_eq!(similarity("capital", "capital"), 1.0);
+        assert_eq!(similarity("abcd", "xyz"), 0.0);
+        assert_eq!(similarity("abc", "abd"), 0.5);
+        assert_eq!(similarity("", ""), 0.0);
+        assert_eq!(similarity("", "abc"), 0.0);
+    }
+
+    #[test]
+    fn groups_similar_contents() {
+        let contents = [
+            (1, "what is the capital of france?".to_owned()),
+            (2, "".to_owned()),
+            (3, "an unrelated card".to_owned()),
+            (4, "what is the capital of france".to_owned()),
+            (5, "".to_owned()),
+            (6, "an unrelated card".to_owned()),
+            (7, "what is the capital of germany?".to_owned()),
+        ];
+        assert_eq!(group(&contents), [vec![1, 4], vec![3, 6]]);
+        assert!(group(&[]).is_empty());
+    }
+
+    #[test]
+    fn finds_near_duplicates_among_many_contents() {
+        // Random words, so that the contents differ from each other.
+        let mut seed = 1u64;
+        let mut contents = (1..=5000)
+            .map(|id| {
+                let content = (0..60)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        b"abcdefghij "[(seed >> 33) as usize % 11] as char
+                    })
+                    .collect::<String>();
+                (id, content)
+            })
+            .collect::<Vec<_>>();
+        let mut changed = contents[99].1.clone();
+        changed.replace_range(30..31, "x");
+        contents.push((5001, changed));
+        contents.push((5002, contents[4000].1.clone()));
+        contents.push((5003, "a".to_owned()));
+        contents.push((5004, "a".to_owned()));
+
+        assert_eq!(
+            group(&contents),
+            [vec![100, 5001], vec![4001, 5002], vec![5003, 5004]]
+        );
+    }
+}
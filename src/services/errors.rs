@@ -0,0 +1,64 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc, sync::Arc};
+
+use dioxus::prelude::ScopeState;
+
+pub fn use_error_log(cx: &ScopeState) -> &ErrorLog {
+    &*cx.use_hook(|_| cx.consume_context::<Rc<ErrorLog>>().unwrap())
+}
+
+// Errors the user can recover from, shown until they are dismissed.
+#[derive(Default)]
+pub struct ErrorLog {
+    errors: RefCell<Vec<String>>,
+    on_change: RefCell<Option<Arc<dyn Fn()>>>,
+}
+
+impl ErrorLog {
+    // `on_change` re-renders the component that shows the errors.
+    pub fn subscribe(&self, on_change: Arc<dyn Fn()>) {
+        *self.on_change.borrow_mut() = Some(on_change);
+    }
+
+    // An error that is already shown isn't repeated, so a page that fails on
+    // every render reports it once.
+    pub fn report<E: Display>(&self, err: E) {
+        let message = err.to_string();
+        let mut errors = self.errors.borrow_mut();
+        if errors.contains(&message) {
+            return;
+        }
+        errors.push(message);
+        drop(errors);
+        self.notify();
+    }
+
+    // Reports the error, if any, and returns the value otherwise.
+    pub fn ok<T, E: Display>(&self, result: Result<T, E>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.report(err);
+                None
+            }
+        }
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.borrow().clone()
+    }
+
+    pub fn dismiss(&self, index: usize) {
+        let mut errors = self.errors.borrow_mut();
+        if index < errors.len() {
+            errors.remove(index);
+        }
+        drop(errors);
+        self.notify();
+    }
+
+    fn notify(&self) {
+        if let Some(on_change) = &*self.on_change.borrow() {
+            on_change();
+        }
+    }
+}
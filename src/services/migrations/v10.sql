@@ -0,0 +1,24 @@
+-- The `changed_at` of each earlier version a card's content is based on, so
+-- that sync can tell a newer version from a conflicting one.
+ALTER TABLE cards ADD COLUMN ancestors TEXT DEFAULT '' NOT NULL;
+
+DROP TRIGGER cards_sync_change;
+
+CREATE TRIGGER cards_sync_change AFTER UPDATE OF content, note_id, suspended, deleted_at ON cards
+WHEN new.changed_at IS old.changed_at BEGIN
+    UPDATE cards SET
+        changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+        ancestors = ancestors || old.changed_at || ' '
+    WHERE card_id = new.card_id;
+END;
+
+-- The other copy's version of cards whose content was changed in both copies,
+-- kept until the user settles them.
+CREATE TABLE card_conflicts (
+    card_id INTEGER PRIMARY KEY,
+    content TEXT NOT NULL,
+    changed_at TEXT NOT NULL,
+    ancestors TEXT NOT NULL,
+    FOREIGN KEY (card_id) REFERENCES cards (card_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Where this copy stands with the sync server it last synced with: the last
+-- of the pushed changes it pulled, and when the changes it pushed last were
+-- taken. Another server or user starts over.
+ALTER TABLE metadata ADD COLUMN sync_server TEXT;
+
+ALTER TABLE metadata ADD COLUMN sync_pulled INTEGER DEFAULT 0 NOT NULL;
+
+ALTER TABLE metadata ADD COLUMN sync_pushed_at TEXT DEFAULT '' NOT NULL;
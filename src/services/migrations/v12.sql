@@ -0,0 +1,12 @@
+-- Like cards, tags get an id that is the same in every copy of the
+-- collection. Existing tags get a random one like new tags, since sync
+-- matches tags by name and settles on one id for each name.
+ALTER TABLE tags ADD COLUMN guid TEXT;
+
+UPDATE tags SET guid = lower(hex(randomblob(16)));
+
+CREATE UNIQUE INDEX tags_guid ON tags (guid);
+
+CREATE TRIGGER tags_guid_insert AFTER INSERT ON tags WHEN new.guid IS NULL BEGIN
+    UPDATE tags SET guid = lower(hex(randomblob(16))) WHERE tag_id = new.tag_id;
+END;
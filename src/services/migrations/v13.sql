@@ -0,0 +1,21 @@
+-- Only the changes sync carries make a new version of a card. Notes aren't
+-- synced, so a card that loses its note, as sync's own merges detach them,
+-- keeps its version, and so does an update that leaves the card as it was.
+--
+-- A card remembers its last 100 earlier versions, at 24 characters with the
+-- space after each stamp, so that the list doesn't grow with every edit. A
+-- copy whose version is older than that conflicts with this one instead of
+-- being replaced by it.
+DROP TRIGGER cards_sync_change;
+
+CREATE TRIGGER cards_sync_change AFTER UPDATE OF content, suspended, deleted_at ON cards
+WHEN new.changed_at IS old.changed_at AND (
+    new.content IS NOT old.content
+    OR new.suspended IS NOT old.suspended
+    OR new.deleted_at IS NOT old.deleted_at
+) BEGIN
+    UPDATE cards SET
+        changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+        ancestors = substr(ancestors || old.changed_at || ' ', -2400)
+    WHERE card_id = new.card_id;
+END;
@@ -0,0 +1,113 @@
+CREATE TABLE note_types (
+    note_type_id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE note_fields (
+    field_id INTEGER PRIMARY KEY,
+    note_type_id INTEGER NOT NULL,
+    ordinal INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE (note_type_id, name)
+    FOREIGN KEY (note_type_id) REFERENCES note_types (note_type_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE TABLE note_templates (
+    template_id INTEGER PRIMARY KEY,
+    note_type_id INTEGER NOT NULL,
+    ordinal INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    FOREIGN KEY (note_type_id) REFERENCES note_types (note_type_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE TABLE notes (
+    note_id INTEGER PRIMARY KEY,
+    note_type_id INTEGER NOT NULL,
+    FOREIGN KEY (note_type_id) REFERENCES note_types (note_type_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE TABLE note_values (
+    note_id INTEGER NOT NULL,
+    field_id INTEGER NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (note_id, field_id)
+    FOREIGN KEY (note_id) REFERENCES notes (note_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+    FOREIGN KEY (field_id) REFERENCES note_fields (field_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+ALTER TABLE cards ADD COLUMN note_id INTEGER
+    REFERENCES notes (note_id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE cards ADD COLUMN template_id INTEGER
+    REFERENCES note_templates (template_id) ON UPDATE CASCADE ON DELETE SET NULL;
+
+INSERT INTO note_types (note_type_id, name)
+    VALUES  (1, "Basic"),
+            (2, "Vocabulary");
+
+INSERT INTO note_fields (field_id, note_type_id, ordinal, name)
+    VALUES  (1, 1, 0, "Front"),
+            (2, 1, 1, "Back"),
+            (3, 2, 0, "Front"),
+            (4, 2, 1, "Back"),
+            (5, 2, 2, "Example"),
+            (6, 2, 3, "Audio");
+
+INSERT INTO note_templates (template_id, note_type_id, ordinal, name, content)
+    VALUES  (1, 1, 0, "Card 1", "{{Front}}{{#Back}}
+
+---
+
+{{Back}}{{/Back}}"),
+            (2, 2, 0, "Recognition", "{{Front}}{{#Audio}}
+
+{{Audio}}{{/Audio}}
+
+---
+
+{{Back}}{{#Example}}
+
+{{Example}}{{/Example}}"),
+            (3, 2, 1, "Production", "{{Back}}
+
+---
+
+{{Front}}{{#Audio}}
+
+{{Audio}}{{/Audio}}{{#Example}}
+
+{{Example}}{{/Example}}");
+
+-- Existing cards become Basic notes, split at the first separator. That is a
+-- line of just `---`, which the editor shows as a rule, not dashes in text.
+INSERT INTO notes (note_id, note_type_id)
+    SELECT card_id, 1 FROM cards;
+
+CREATE TEMP TABLE card_split AS
+    SELECT card_id, content, char(10) || content || char(10) AS lines,
+        instr(char(10) || content || char(10), char(10) || '---' || char(10)) AS separator
+    FROM cards;
+
+INSERT INTO note_values (note_id, field_id, value)
+    SELECT card_id, 1, CASE
+        WHEN separator > 0
+        THEN trim(substr(lines, 1, separator - 1), char(9, 10, 13, 32))
+        ELSE content
+    END FROM card_split;
+
+INSERT INTO note_values (note_id, field_id, value)
+    SELECT card_id, 2, CASE
+        WHEN separator > 0
+        THEN trim(substr(lines, separator + 5), char(9, 10, 13, 32))
+        ELSE ''
+    END FROM card_split;
+
+DROP TABLE card_split;
+
+UPDATE cards SET note_id = card_id, template_id = 1;
@@ -0,0 +1,20 @@
+CREATE VIRTUAL TABLE cards_fts USING fts5(
+    content,
+    content = 'cards',
+    content_rowid = 'card_id'
+);
+
+CREATE TRIGGER cards_fts_insert AFTER INSERT ON cards BEGIN
+    INSERT INTO cards_fts (rowid, content) VALUES (new.card_id, new.content);
+END;
+
+CREATE TRIGGER cards_fts_delete AFTER DELETE ON cards BEGIN
+    INSERT INTO cards_fts (cards_fts, rowid, content) VALUES ('delete', old.card_id, old.content);
+END;
+
+CREATE TRIGGER cards_fts_update AFTER UPDATE OF content ON cards BEGIN
+    INSERT INTO cards_fts (cards_fts, rowid, content) VALUES ('delete', old.card_id, old.content);
+    INSERT INTO cards_fts (rowid, content) VALUES (new.card_id, new.content);
+END;
+
+INSERT INTO cards_fts (cards_fts) VALUES ('rebuild');
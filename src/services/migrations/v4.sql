@@ -0,0 +1,5 @@
+CREATE TABLE saved_searches (
+    saved_search_id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    query TEXT NOT NULL
+);
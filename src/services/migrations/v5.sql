@@ -0,0 +1 @@
+ALTER TABLE cards ADD COLUMN suspended INTEGER DEFAULT 0 NOT NULL;
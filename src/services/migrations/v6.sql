@@ -0,0 +1,3 @@
+ALTER TABLE cards ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX cards_deleted_at ON cards (deleted_at);
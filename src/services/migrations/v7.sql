@@ -0,0 +1,10 @@
+CREATE TABLE card_revisions (
+    revision_id INTEGER PRIMARY KEY,
+    card_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    created_at TEXT DEFAULT (datetime('now')) NOT NULL,
+    FOREIGN KEY (card_id) REFERENCES cards (card_id)
+        ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX card_revisions_card_id ON card_revisions (card_id);
@@ -0,0 +1,13 @@
+-- Creation times of existing cards are unknown and stay empty.
+ALTER TABLE cards ADD COLUMN created_at TEXT;
+
+ALTER TABLE cards ADD COLUMN modified_at TEXT;
+
+UPDATE cards SET modified_at = (
+    SELECT MAX(created_at) FROM card_revisions
+    WHERE card_revisions.card_id = cards.card_id
+);
+
+CREATE INDEX cards_created_at ON cards (created_at);
+
+CREATE INDEX cards_modified_at ON cards (modified_at);
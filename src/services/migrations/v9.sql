@@ -0,0 +1,120 @@
+-- Sync matches cards by `guid` and tags by name. Existing cards get a random
+-- one like new cards.
+ALTER TABLE cards ADD COLUMN guid TEXT;
+
+-- When the content, suspension or trash state and the review state of a card
+-- last changed.
+ALTER TABLE cards ADD COLUMN changed_at TEXT;
+
+ALTER TABLE cards ADD COLUMN reviewed_at TEXT;
+
+-- An id derived from the row of a card that existed before sync, which is the
+-- same in every copy that was synced by replacing the whole database. Sync
+-- matches such cards by it when their guids differ.
+ALTER TABLE cards ADD COLUMN guid_hint TEXT;
+
+ALTER TABLE tags ADD COLUMN changed_at TEXT;
+
+ALTER TABLE card_tag ADD COLUMN changed_at TEXT;
+
+-- In the same format as the stamps the triggers below set, since sync
+-- compares them as text.
+UPDATE cards SET
+    guid = lower(hex(randomblob(16))),
+    guid_hint = lower(hex(card_id || '/' || ifnull(created_at, ''))),
+    changed_at = ifnull(
+        strftime('%Y-%m-%d %H:%M:%f', coalesce(modified_at, created_at)),
+        '1970-01-01 00:00:00.000'
+    ),
+    reviewed_at = '1970-01-01 00:00:00.000';
+
+UPDATE tags SET changed_at = '1970-01-01 00:00:00.000';
+
+UPDATE card_tag SET changed_at = '1970-01-01 00:00:00.000';
+
+CREATE UNIQUE INDEX cards_guid ON cards (guid);
+
+-- Rows deleted for good, so that sync deletes them from the other copy too.
+CREATE TABLE deleted_cards (
+    guid TEXT PRIMARY KEY NOT NULL,
+    deleted_at TEXT NOT NULL
+);
+
+CREATE TABLE deleted_tags (
+    name TEXT PRIMARY KEY NOT NULL,
+    deleted_at TEXT NOT NULL
+);
+
+CREATE TABLE deleted_card_tags (
+    card_guid TEXT NOT NULL,
+    tag_name TEXT NOT NULL,
+    deleted_at TEXT NOT NULL,
+    PRIMARY KEY (card_guid, tag_name)
+);
+
+-- The stamps have milliseconds so that changes made in the same second on
+-- two machines can still be ordered. Values set by the statement itself, as
+-- sync does, are kept.
+CREATE TRIGGER cards_sync_insert AFTER INSERT ON cards BEGIN
+    UPDATE cards SET
+        guid = ifnull(new.guid, lower(hex(randomblob(16)))),
+        changed_at = ifnull(new.changed_at, strftime('%Y-%m-%d %H:%M:%f', 'now')),
+        reviewed_at = ifnull(new.reviewed_at, strftime('%Y-%m-%d %H:%M:%f', 'now'))
+    WHERE card_id = new.card_id;
+    DELETE FROM deleted_cards WHERE guid = new.guid;
+END;
+
+CREATE TRIGGER cards_sync_change AFTER UPDATE OF content, note_id, suspended, deleted_at ON cards
+WHEN new.changed_at IS old.changed_at BEGIN
+    UPDATE cards SET changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE card_id = new.card_id;
+END;
+
+CREATE TRIGGER cards_sync_review
+AFTER UPDATE OF due_date, due_days, recall_attempts, successful_recalls ON cards
+WHEN new.reviewed_at IS old.reviewed_at BEGIN
+    UPDATE cards SET reviewed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE card_id = new.card_id;
+END;
+
+CREATE TRIGGER cards_sync_delete AFTER DELETE ON cards WHEN old.guid IS NOT NULL BEGIN
+    INSERT OR IGNORE INTO deleted_cards (guid, deleted_at)
+    VALUES (old.guid, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+CREATE TRIGGER tags_sync_insert AFTER INSERT ON tags BEGIN
+    UPDATE tags SET changed_at = ifnull(new.changed_at, strftime('%Y-%m-%d %H:%M:%f', 'now'))
+    WHERE tag_id = new.tag_id;
+    DELETE FROM deleted_tags WHERE name = new.name;
+END;
+
+-- To the other copy a renamed tag is a deleted one and a new one.
+CREATE TRIGGER tags_sync_rename AFTER UPDATE OF name ON tags
+WHEN new.name IS NOT old.name BEGIN
+    INSERT OR IGNORE INTO deleted_tags (name, deleted_at)
+    VALUES (old.name, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+    DELETE FROM deleted_tags WHERE name = new.name;
+    UPDATE tags SET changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE tag_id = new.tag_id;
+    UPDATE card_tag SET changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE tag_id = new.tag_id;
+END;
+
+CREATE TRIGGER tags_sync_delete AFTER DELETE ON tags BEGIN
+    INSERT OR IGNORE INTO deleted_tags (name, deleted_at)
+    VALUES (old.name, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+CREATE TRIGGER card_tag_sync_insert AFTER INSERT ON card_tag BEGIN
+    UPDATE card_tag SET changed_at = ifnull(new.changed_at, strftime('%Y-%m-%d %H:%M:%f', 'now'))
+    WHERE card_id = new.card_id AND tag_id = new.tag_id;
+    DELETE FROM deleted_card_tags
+    WHERE card_guid = (SELECT guid FROM cards WHERE card_id = new.card_id)
+        AND tag_name = (SELECT name FROM tags WHERE tag_id = new.tag_id);
+END;
+
+CREATE TRIGGER card_tag_sync_delete AFTER DELETE ON card_tag BEGIN
+    INSERT OR IGNORE INTO deleted_card_tags (card_guid, tag_name, deleted_at)
+    SELECT cards.guid, tags.name, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM cards, tags
+    WHERE cards.card_id = old.card_id AND tags.tag_id = old.tag_id;
+END;
@@ -1,4 +1,13 @@
 pub mod archive;
+pub mod autosave;
+pub mod backup;
 pub mod config;
 pub mod database;
+pub mod diff;
+pub mod duplicates;
+pub mod errors;
 pub mod markdown;
+pub mod search;
+pub mod sync;
+pub mod tag_tree;
+pub mod template;
@@ -0,0 +1,499 @@
+use std::fmt;
+
+use rusqlite::{functions::FunctionFlags, types::Value, Connection};
+
+// Cards failing at least this many reviews are considered leeches.
+pub const LEECH_FAILURES: i64 = 8;
+
+// Words starting with one of these and a colon are field searches.
+const FIELDS: [&str; 7] = ["tag", "due", "attempts", "ratio", "added", "edited", "is"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub negated: bool,
+    pub term: Term,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Word(String),
+    Phrase(String),
+    Tag(String),
+    Due(Comparison, i64),
+    Attempts(Comparison, i64),
+    Ratio(Comparison, f64),
+    // Cards added or edited in the last this many days, 1 being today.
+    Added(i64),
+    Edited(i64),
+    Is(CardState),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardState {
+    New,
+    Due,
+    Leech,
+    Suspended,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnterminatedQuote,
+    EmptyValue(String),
+    InvalidValue(String, String),
+}
+
+// The pieces needed to run a query against `cards`. Positive words and phrases
+// are combined into `fts_match` so results can be ranked, everything else ends
+// up in `condition` with its values in `params`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledQuery {
+    pub condition: String,
+    pub params: Vec<Value>,
+    pub fts_match: Option<String>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut clauses = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let negated = chars.next_if_eq(&'-').is_some();
+
+            if chars.next_if_eq(&'"').is_some() {
+                let phrase = read_quoted(&mut chars)?;
+                if !phrase.trim().is_empty() {
+                    clauses.push(Clause {
+                        negated,
+                        term: Term::Phrase(phrase),
+                    });
+                }
+                continue;
+            }
+
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                word.push(c);
+            }
+
+            // Allows quoted values such as `tag:"Two words"`.
+            if word.ends_with(':') && chars.next_if_eq(&'"').is_some() {
+                word.push_str(&read_quoted(&mut chars)?);
+            }
+
+            if word.is_empty() {
+                // A lone `-` is treated as a word rather than a negation.
+                if negated {
+                    clauses.push(Clause {
+                        negated: false,
+                        term: Term::Word("-".to_owned()),
+                    });
+                }
+                continue;
+            }
+
+            clauses.push(Clause {
+                negated,
+                term: parse_term(&word)?,
+            });
+        }
+
+        Ok(Self { clauses })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    // Whether the query matches card text, which is when results have a rank
+    // and a snippet.
+    pub fn has_text_terms(&self) -> bool {
+        self.clauses
+            .iter()
+            .any(|clause| !clause.negated && matches!(clause.term, Term::Word(_) | Term::Phrase(_)))
+    }
+
+    pub fn compile(&self) -> CompiledQuery {
+        let mut conditions = vec!["1".to_owned()];
+        let mut params = Vec::new();
+        let mut fts_terms = Vec::new();
+
+        for clause in &self.clauses {
+            let condition = match &clause.term {
+                Term::Word(word) => {
+                    let term = format!("{}*", quote_fts(word));
+                    match clause.negated {
+                        false => {
+                            fts_terms.push(term);
+                            continue;
+                        }
+                        true => {
+                            params.push(Value::Text(term));
+                            NOT_MATCHING_TEXT.to_owned()
+                        }
+                    }
+                }
+                Term::Phrase(phrase) => {
+                    let term = quote_fts(phrase);
+                    match clause.negated {
+                        false => {
+                            fts_terms.push(term);
+                            continue;
+                        }
+                        true => {
+                            params.push(Value::Text(term));
+                            NOT_MATCHING_TEXT.to_owned()
+                        }
+                    }
+                }
+                Term::Tag(name) => {
+                    let name = name.to_lowercase();
+                    params.push(Value::Text(name.clone()));
+                    params.push(Value::Text(name.clone()));
+                    params.push(Value::Text(name));
+                    format!("{}{HAS_TAG_NAMED}", negation(clause))
+                }
+                Term::Due(comparison, days) => {
+                    params.push(Value::Integer(*days));
+                    format!(
+                        "{}(julianday(cards.due_date) - julianday(date('now')) {comparison} ?)",
+                        negation(clause)
+                    )
+                }
+                Term::Attempts(comparison, attempts) => {
+                    params.push(Value::Integer(*attempts));
+                    format!("{}(cards.recall_attempts {comparison} ?)", negation(clause))
+                }
+                Term::Ratio(comparison, ratio) => {
+                    params.push(Value::Real(*ratio));
+                    format!(
+                        "{}IFNULL(CAST(cards.successful_recalls AS REAL) / NULLIF(cards.recall_attempts, 0) {comparison} ?, 0)",
+                        negation(clause)
+                    )
+                }
+                Term::Added(days) => {
+                    params.push(Value::Integer(*days));
+                    format!(
+                        "{}IFNULL(date(cards.created_at) > date('now', '-' || ? || ' days'), 0)",
+                        negation(clause)
+                    )
+                }
+                Term::Edited(days) => {
+                    params.push(Value::Integer(*days));
+                    format!(
+                        "{}IFNULL(date(cards.modified_at) > date('now', '-' || ? || ' days'), 0)",
+                        negation(clause)
+                    )
+                }
+                Term::Is(CardState::New) => {
+                    format!("{}(cards.recall_attempts = 0)", negation(clause))
+                }
+                Term::Is(CardState::Due) => {
+                    format!(
+                        "{}(cards.due_date <= date('now') AND cards.suspended = 0)",
+                        negation(clause)
+                    )
+                }
+                Term::Is(CardState::Suspended) => {
+                    format!("{}(cards.suspended = 1)", negation(clause))
+                }
+                Term::Is(CardState::Leech) => {
+                    params.push(Value::Integer(LEECH_FAILURES));
+                    format!(
+                        "{}(cards.recall_attempts - cards.successful_recalls >= ?)",
+                        negation(clause)
+                    )
+                }
+            };
+
+            conditions.push(condition);
+        }
+
+        CompiledQuery {
+            condition: conditions.join(" AND "),
+            params,
+            fts_match: match fts_terms.is_empty() {
+                true => None,
+                false => Some(fts_terms.join(" ")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        })
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote => write!(f, "Missing closing quote"),
+            ParseError::EmptyValue(field) => write!(f, "Missing value for \"{field}:\""),
+            ParseError::InvalidValue(field, value) => {
+                write!(f, "Invalid value \"{value}\" for \"{field}:\"")
+            }
+        }
+    }
+}
+
+fn parse_term(word: &str) -> Result<Term, ParseError> {
+    // Words like `12:30` or `http://...` that don't start with a known field
+    // are searched for as they are.
+    let (field, value) = match word.split_once(':') {
+        Some((field, value)) if FIELDS.contains(&field.to_lowercase().as_str()) => {
+            (field.to_lowercase(), value)
+        }
+        _ => return Ok(Term::Word(word.to_owned())),
+    };
+
+    if value.is_empty() {
+        return Err(ParseError::EmptyValue(field));
+    }
+
+    let invalid = || ParseError::InvalidValue(field.clone(), value.to_owned());
+
+    match field.as_str() {
+        "tag" => Ok(Term::Tag(value.to_owned())),
+        "due" => {
+            let (comparison, days) = parse_comparison(value);
+            Ok(Term::Due(comparison, days.parse().map_err(|_| invalid())?))
+        }
+        "attempts" => {
+            let (comparison, attempts) = parse_comparison(value);
+            Ok(Term::Attempts(
+                comparison,
+                attempts.parse().map_err(|_| invalid())?,
+            ))
+        }
+        "ratio" => {
+            let (comparison, ratio) = parse_comparison(value);
+            Ok(Term::Ratio(
+                comparison,
+                ratio.parse().map_err(|_| invalid())?,
+            ))
+        }
+        "added" => match value.parse() {
+            Ok(days) if days > 0 => Ok(Term::Added(days)),
+            _ => Err(invalid()),
+        },
+        "edited" => match value.parse() {
+            Ok(days) if days > 0 => Ok(Term::Edited(days)),
+            _ => Err(invalid()),
+        },
+        "is" => match value.to_lowercase().as_str() {
+            "new" => Ok(Term::Is(CardState::New)),
+            "due" => Ok(Term::Is(CardState::Due)),
+            "leech" => Ok(Term::Is(CardState::Leech)),
+            "suspended" => Ok(Term::Is(CardState::Suspended)),
+            _ => Err(invalid()),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn parse_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest);
+        }
+    }
+
+    (Comparison::Equal, value)
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, ParseError> {
+    let mut quoted = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(quoted);
+        }
+        quoted.push(c);
+    }
+    Err(ParseError::UnterminatedQuote)
+}
+
+fn negation(clause: &Clause) -> &'static str {
+    match clause.negated {
+        true => "NOT ",
+        false => "",
+    }
+}
+
+// Quotes user input so it can't be parsed as FTS5 syntax.
+fn quote_fts(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+const NOT_MATCHING_TEXT: &str = r#"
+    cards.card_id NOT IN (
+        SELECT rowid FROM cards_fts WHERE cards_fts MATCH ?
+    )
+"#;
+
+// Matches a tag by name, case insensitively, including all of its descendants.
+const HAS_TAG_NAMED: &str = r#"
+    EXISTS (
+        SELECT 1 FROM card_tag ct
+        JOIN tags t ON t.tag_id = ct.tag_id
+        WHERE ct.card_id = cards.card_id
+        AND (casefold(t.name) = ? OR substr(casefold(t.name), 1, length(?) + 2) = ? || '::')
+    )
+"#;
+
+// Adds the functions compiled queries use to `connection`. SQLite's `lower`
+// only lowercases ASCII letters, so `casefold` lowercases names the way the
+// searched name is.
+pub fn add_functions(connection: &Connection) -> rusqlite::Result<()> {
+    connection.create_scalar_function(
+        "casefold",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(ctx.get::<String>(0)?.to_lowercase()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(input: &str) -> Vec<(bool, Term)> {
+        Query::parse(input)
+            .unwrap()
+            .clauses
+            .into_iter()
+            .map(|clause| (clause.negated, clause.term))
+            .collect()
+    }
+
+    #[test]
+    fn parses_words_and_phrases() {
+        assert_eq!(
+            terms(r#"  dog -cat "big house" -"old tree" "#),
+            [
+                (false, Term::Word("dog".to_owned())),
+                (true, Term::Word("cat".to_owned())),
+                (false, Term::Phrase("big house".to_owned())),
+                (true, Term::Phrase("old tree".to_owned())),
+            ]
+        );
+        assert_eq!(terms("- \"\""), [(false, Term::Word("-".to_owned()))]);
+        assert!(Query::parse("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(
+            terms(r#"tag:"Two words" -TAG:verbs due:<=3 attempts:>2 ratio:0.5"#),
+            [
+                (false, Term::Tag("Two words".to_owned())),
+                (true, Term::Tag("verbs".to_owned())),
+                (false, Term::Due(Comparison::LessOrEqual, 3)),
+                (false, Term::Attempts(Comparison::Greater, 2)),
+                (false, Term::Ratio(Comparison::Equal, 0.5)),
+            ]
+        );
+        assert_eq!(
+            terms("added:7 edited:1 is:New -is:suspended"),
+            [
+                (false, Term::Added(7)),
+                (false, Term::Edited(1)),
+                (false, Term::Is(CardState::New)),
+                (true, Term::Is(CardState::Suspended)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_fields_as_words() {
+        assert_eq!(
+            terms("12:30 http://example.com note:"),
+            [
+                (false, Term::Word("12:30".to_owned())),
+                (false, Term::Word("http://example.com".to_owned())),
+                (false, Term::Word("note:".to_owned())),
+            ]
+        );
+        assert_eq!(terms(":tag"), [(false, Term::Word(":tag".to_owned()))]);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(
+            Query::parse(r#"dog "cat"#),
+            Err(ParseError::UnterminatedQuote)
+        );
+        assert_eq!(
+            Query::parse(r#"tag:"cat"#),
+            Err(ParseError::UnterminatedQuote)
+        );
+        assert_eq!(
+            Query::parse("tag:"),
+            Err(ParseError::EmptyValue("tag".to_owned()))
+        );
+        for (input, field, value) in [
+            ("due:soon", "due", "soon"),
+            ("attempts:<x", "attempts", "<x"),
+            ("added:0", "added", "0"),
+            ("is:old", "is", "old"),
+        ] {
+            assert_eq!(
+                Query::parse(input),
+                Err(ParseError::InvalidValue(field.to_owned(), value.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn compiles_text_terms_into_the_fts_match() {
+        let query = Query::parse(r#"dog "big house" -cat tag:x"#).unwrap();
+        assert!(query.has_text_terms());
+
+        let compiled = query.compile();
+        assert_eq!(compiled.fts_match.as_deref(), Some(r#""dog"* "big house""#));
+        assert_eq!(
+            compiled.params,
+            [
+                Value::Text(r#""cat"*"#.to_owned()),
+                Value::Text("x".to_owned()),
+                Value::Text("x".to_owned()),
+                Value::Text("x".to_owned()),
+            ]
+        );
+        assert!(!Query::parse("-dog due:1").unwrap().has_text_terms());
+    }
+}
@@ -0,0 +1,565 @@
+use std::{fmt, ops::AddAssign};
+
+use rusqlite::Connection;
+
+// Rows changed in one copy of the collection by a sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncChanges {
+    pub cards_added: usize,
+    pub cards_updated: usize,
+    pub cards_deleted: usize,
+    pub tags_added: usize,
+    pub tags_deleted: usize,
+    pub card_tags_added: usize,
+    pub card_tags_deleted: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    // Changes made to this copy.
+    pub received: SyncChanges,
+    // Changes that reach the other copy with the next save.
+    pub sent: SyncChanges,
+}
+
+impl SyncChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl AddAssign for SyncChanges {
+    fn add_assign(&mut self, other: Self) {
+        self.cards_added += other.cards_added;
+        self.cards_updated += other.cards_updated;
+        self.cards_deleted += other.cards_deleted;
+        self.tags_added += other.tags_added;
+        self.tags_deleted += other.tags_deleted;
+        self.card_tags_added += other.card_tags_added;
+        self.card_tags_deleted += other.card_tags_deleted;
+    }
+}
+
+impl fmt::Display for SyncChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [
+            (self.cards_added, "card", "added"),
+            (self.cards_updated, "card", "updated"),
+            (self.cards_deleted, "card", "deleted"),
+            (self.tags_added, "tag", "added"),
+            (self.tags_deleted, "tag", "deleted"),
+            (self.card_tags_added, "card tag", "added"),
+            (self.card_tags_deleted, "card tag", "removed"),
+        ]
+        .into_iter()
+        .filter(|(count, _, _)| *count > 0)
+        .map(|(count, name, action)| match count {
+            1 => format!("1 {name} {action}"),
+            _ => format!("{count} {name}s {action}"),
+        })
+        .collect::<Vec<_>>();
+
+        match parts.is_empty() {
+            true => write!(f, "no changes"),
+            false => write!(f, "{}", parts.join(", ")),
+        }
+    }
+}
+
+// The earlier versions a card remembers, as the `cards_sync_change` trigger
+// keeps them. Each stamp takes 24 characters with the space after it.
+const MAX_ANCESTORS: usize = 100;
+
+const TOMBSTONE_TABLES: [&str; 3] = ["deleted_cards", "deleted_tags", "deleted_card_tags"];
+
+// Cards in conflict keep their content in both copies until they are settled.
+const HELD_BACK_CARDS: &str =
+    "SELECT guid FROM main.cards JOIN main.card_conflicts USING (card_id)";
+
+// Merges this database and the one attached as `other` in both directions.
+pub fn sync(conn: &Connection, other: &str) -> rusqlite::Result<SyncReport> {
+    settle_card_guids(conn, other)?;
+    record_conflicts(conn, other)?;
+
+    // Merging into `other` first counts what this copy adds to it.
+    let sent = merge(conn, "main", other)?;
+    let received = merge(conn, other, "main")?;
+
+    Ok(SyncReport { received, sent })
+}
+
+// Merges the changes in the database attached as `other` into this one.
+pub fn receive(conn: &Connection, other: &str) -> rusqlite::Result<SyncChanges> {
+    settle_card_guids(conn, other)?;
+    record_conflicts(conn, other)?;
+    merge(conn, other, "main")
+}
+
+// Turns a copy of the collection into the changes made after `since`: the
+// rows changed since then, the cards and tags their links refer to and the
+// newer tombstones. Returns what is left, where a card created since then
+// counts as added and any other as updated.
+pub fn keep_changes_since(conn: &Connection, since: &str) -> rusqlite::Result<SyncChanges> {
+    // Rows removed here aren't deleted, so the tombstones they leave are
+    // thrown away again.
+    for table in TOMBSTONE_TABLES {
+        conn.execute(
+            &format!(
+                "CREATE TEMP TABLE kept_{table} AS SELECT * FROM {table} WHERE deleted_at > ?"
+            ),
+            [since],
+        )?;
+    }
+
+    for sql in [
+        "DELETE FROM card_tag WHERE changed_at <= ?",
+        r#"
+        DELETE FROM cards WHERE changed_at <= ?1 AND reviewed_at <= ?1
+            AND card_id NOT IN (SELECT card_id FROM card_tag)
+        "#,
+        "DELETE FROM tags WHERE changed_at <= ? AND tag_id NOT IN (SELECT tag_id FROM card_tag)",
+    ] {
+        conn.execute(sql, [since])?;
+    }
+    conn.execute_batch(
+        r#"
+        DELETE FROM card_revisions;
+        DELETE FROM card_conflicts;
+        DELETE FROM saved_searches;
+        DELETE FROM note_values WHERE note_id NOT IN (
+            SELECT note_id FROM cards WHERE note_id IS NOT NULL
+        );
+        DELETE FROM notes WHERE note_id NOT IN (
+            SELECT note_id FROM cards WHERE note_id IS NOT NULL
+        );
+        "#,
+    )?;
+
+    for table in TOMBSTONE_TABLES {
+        conn.execute_batch(&format!(
+            r#"
+            DELETE FROM main.{table};
+            INSERT INTO main.{table} SELECT * FROM kept_{table};
+            DROP TABLE kept_{table};
+            "#
+        ))?;
+    }
+
+    // `created_at` only has whole seconds.
+    let count = |sql: &str| conn.query_row(sql, [since], |row| row.get(0));
+    Ok(SyncChanges {
+        cards_added: count("SELECT COUNT(*) FROM cards WHERE created_at >= substr(?, 1, 19)")?,
+        cards_updated: count(
+            r#"
+            SELECT COUNT(*) FROM cards
+            WHERE (changed_at > ?1 OR reviewed_at > ?1)
+                AND NOT ifnull(created_at >= substr(?1, 1, 19), false)
+            "#,
+        )?,
+        cards_deleted: count("SELECT COUNT(*) FROM deleted_cards WHERE deleted_at > ?")?,
+        tags_added: count("SELECT COUNT(*) FROM tags WHERE changed_at > ?")?,
+        tags_deleted: count("SELECT COUNT(*) FROM deleted_tags WHERE deleted_at > ?")?,
+        card_tags_added: count("SELECT COUNT(*) FROM card_tag WHERE changed_at > ?")?,
+        card_tags_deleted: count("SELECT COUNT(*) FROM deleted_card_tags WHERE deleted_at > ?")?,
+    })
+}
+
+// Makes this database, a collection restored from a backup, win over the one
+// it replaces, attached as `other`, and over the copies that one was synced
+// with. The rows the restore changed become newer than their versions there,
+// rows only `other` has get tombstones and `other`'s place with the sync
+// server is kept, so that the next sync sends the restored collection out
+// instead of bringing the newer changes back.
+pub fn supersede(conn: &Connection, other: &str) -> rusqlite::Result<()> {
+    settle_card_guids(conn, other)?;
+    conn.execute_batch(&format!(
+        r#"
+        INSERT OR REPLACE INTO main.deleted_cards (guid, deleted_at)
+        SELECT guid, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM {other}.cards
+        WHERE guid NOT IN (SELECT guid FROM main.cards);
+
+        INSERT OR REPLACE INTO main.deleted_tags (name, deleted_at)
+        SELECT name, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM {other}.tags
+        WHERE name NOT IN (SELECT name FROM main.tags);
+
+        INSERT OR REPLACE INTO main.deleted_card_tags (card_guid, tag_name, deleted_at)
+        SELECT oc.guid, ot.name, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM {other}.card_tag AS oct
+        JOIN {other}.cards AS oc ON oc.card_id = oct.card_id
+        JOIN {other}.tags AS ot ON ot.tag_id = oct.tag_id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM main.card_tag AS mct
+            JOIN main.cards AS mc ON mc.card_id = mct.card_id
+            JOIN main.tags AS mt ON mt.tag_id = mct.tag_id
+            WHERE mc.guid = oc.guid AND mt.name = ot.name
+        );
+
+        INSERT OR IGNORE INTO main.deleted_cards SELECT * FROM {other}.deleted_cards
+        WHERE guid NOT IN (SELECT guid FROM main.cards);
+
+        INSERT OR IGNORE INTO main.deleted_tags SELECT * FROM {other}.deleted_tags
+        WHERE name NOT IN (SELECT name FROM main.tags);
+
+        -- The restored content is based on both its own earlier versions and
+        -- the one it replaces, so it isn't a conflict for the other copies.
+        UPDATE main.cards SET
+            ancestors = {ancestors},
+            changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+        WHERE NOT EXISTS (
+            SELECT 1 FROM {other}.cards AS oc
+            WHERE oc.guid = cards.guid AND oc.content = cards.content
+                AND oc.suspended = cards.suspended AND oc.deleted_at IS cards.deleted_at
+        );
+
+        UPDATE main.cards SET reviewed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+        WHERE NOT EXISTS (
+            SELECT 1 FROM {other}.cards AS oc
+            WHERE oc.guid = cards.guid AND oc.due_date = cards.due_date
+                AND oc.due_days = cards.due_days AND oc.recall_attempts = cards.recall_attempts
+                AND oc.successful_recalls = cards.successful_recalls
+        );
+
+        UPDATE main.tags SET changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+        WHERE name NOT IN (SELECT name FROM {other}.tags);
+
+        UPDATE main.card_tag SET changed_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+        WHERE NOT EXISTS (
+            SELECT 1 FROM {other}.card_tag AS oct
+            JOIN {other}.cards AS oc ON oc.card_id = oct.card_id
+            JOIN {other}.tags AS ot ON ot.tag_id = oct.tag_id
+            JOIN main.cards AS mc ON mc.guid = oc.guid
+            JOIN main.tags AS mt ON mt.name = ot.name
+            WHERE mc.card_id = card_tag.card_id AND mt.tag_id = card_tag.tag_id
+        );
+
+        UPDATE main.metadata SET (sync_server, sync_pulled, sync_pushed_at) = (
+            SELECT sync_server, sync_pulled, sync_pushed_at FROM {other}.metadata
+            WHERE metadata_id = 1
+        )
+        WHERE metadata_id = 1;
+        "#,
+        ancestors = capped_ancestors(&format!(
+            r#"
+            ancestors || changed_at || ' ' || ifnull((
+                SELECT oc.ancestors || oc.changed_at || ' ' FROM {other}.cards AS oc
+                WHERE oc.guid = cards.guid
+            ), '')
+            "#
+        )),
+    ))
+}
+
+// Cards that both copies had before sync came along got a guid in each copy
+// on its own, and are matched by their hint instead. Both copies settle on
+// the smaller guid, as with tags.
+fn settle_card_guids(conn: &Connection, other: &str) -> rusqlite::Result<()> {
+    for (from, into) in [("main", other), (other, "main")] {
+        conn.execute_batch(&format!(
+            r#"
+            CREATE TEMP TABLE settled_guids AS
+            SELECT ic.guid AS old_guid, fc.guid AS new_guid FROM {into}.cards AS ic
+            JOIN {from}.cards AS fc ON fc.guid_hint = ic.guid_hint
+            WHERE fc.guid < ic.guid
+                AND NOT EXISTS (SELECT 1 FROM {into}.cards WHERE guid = fc.guid);
+
+            UPDATE {into}.cards
+            SET guid = (SELECT new_guid FROM temp.settled_guids WHERE old_guid = cards.guid)
+            WHERE guid IN (SELECT old_guid FROM temp.settled_guids);
+
+            UPDATE OR REPLACE {into}.deleted_card_tags
+            SET card_guid = (
+                SELECT new_guid FROM temp.settled_guids WHERE old_guid = card_guid
+            )
+            WHERE card_guid IN (SELECT old_guid FROM temp.settled_guids);
+
+            DROP TABLE temp.settled_guids;
+            "#
+        ))?;
+    }
+    Ok(())
+}
+
+// Queues the cards with different content in both copies where neither
+// version is based on the other, together with the other copy's version. A
+// queued conflict is settled once the other copy's version is based on this
+// one's, when it was settled there.
+fn record_conflicts(conn: &Connection, other: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        r#"
+        DELETE FROM main.card_conflicts WHERE card_id IN (
+            SELECT mc.card_id FROM main.cards AS mc
+            JOIN {other}.cards AS oc ON oc.guid = mc.guid
+            WHERE {oc_based_on_mc}
+        );
+
+        INSERT OR REPLACE INTO main.card_conflicts (card_id, content, changed_at, ancestors)
+        SELECT mc.card_id, oc.content, oc.changed_at, oc.ancestors FROM main.cards AS mc
+        JOIN {other}.cards AS oc ON oc.guid = mc.guid
+        WHERE oc.changed_at != mc.changed_at
+            AND NOT {mc_based_on_oc}
+            AND NOT {oc_based_on_mc}
+            AND oc.content != mc.content;
+        "#,
+        mc_based_on_oc = has_stamp("mc.ancestors", "oc.changed_at"),
+        oc_based_on_mc = has_stamp("oc.ancestors", "mc.changed_at"),
+    ))
+}
+
+// `ancestors`, a list of stamps, cut down to the last `MAX_ANCESTORS`.
+pub fn capped_ancestors(ancestors: &str) -> String {
+    format!("substr({ancestors}, -{})", MAX_ANCESTORS * 24)
+}
+
+// An expression for whether the stamp `stamp` is one of `ancestors`. Stamps
+// have a space in them, and each one in the list is followed by another, so
+// only a whole stamp matches with the spaces around it.
+fn has_stamp(ancestors: &str, stamp: &str) -> String {
+    format!("instr(' ' || {ancestors}, ' ' || {stamp} || ' ') > 0")
+}
+
+// Applies the changes of the database attached as `from` that are newer than
+// those in `into`, row by row. Whichever of a row and its tombstone is newer
+// wins, and the content and the review state of a card are merged separately.
+fn merge(conn: &Connection, from: &str, into: &str) -> rusqlite::Result<SyncChanges> {
+    let mut changes = SyncChanges::default();
+
+    // Tombstones first, so that rows deleted below keep the time they were
+    // deleted in `from`.
+    conn.execute_batch(&format!(
+        r#"
+        INSERT INTO {into}.deleted_cards (guid, deleted_at)
+        SELECT guid, deleted_at FROM {from}.deleted_cards WHERE true
+        ON CONFLICT (guid) DO UPDATE SET deleted_at = max(deleted_at, excluded.deleted_at);
+
+        INSERT INTO {into}.deleted_tags (name, deleted_at)
+        SELECT name, deleted_at FROM {from}.deleted_tags WHERE true
+        ON CONFLICT (name) DO UPDATE SET deleted_at = max(deleted_at, excluded.deleted_at);
+
+        INSERT INTO {into}.deleted_card_tags (card_guid, tag_name, deleted_at)
+        SELECT card_guid, tag_name, deleted_at FROM {from}.deleted_card_tags WHERE true
+        ON CONFLICT (card_guid, tag_name)
+        DO UPDATE SET deleted_at = max(deleted_at, excluded.deleted_at);
+        "#
+    ))?;
+
+    changes.tags_added = conn.execute(
+        &format!(
+            r#"
+            INSERT INTO {into}.tags (name, changed_at, guid)
+            SELECT name, changed_at, CASE
+                WHEN EXISTS (SELECT 1 FROM {into}.tags WHERE guid = ft.guid) THEN NULL
+                ELSE guid
+            END
+            FROM {from}.tags AS ft
+            WHERE NOT EXISTS (SELECT 1 FROM {into}.tags WHERE name = ft.name)
+                AND NOT EXISTS (
+                    SELECT 1 FROM {into}.deleted_tags AS d
+                    WHERE d.name = ft.name AND d.deleted_at >= ft.changed_at
+                )
+            "#
+        ),
+        [],
+    )?;
+
+    // Notes aren't synced, so cards made from a note in the other copy arrive
+    // as plain cards.
+    changes.cards_added = conn.execute(
+        &format!(
+            r#"
+            INSERT INTO {into}.cards (
+                content, due_date, due_days, recall_attempts, successful_recalls, suspended,
+                deleted_at, created_at, modified_at, guid, changed_at, reviewed_at, ancestors,
+                guid_hint
+            )
+            SELECT
+                content, due_date, due_days, recall_attempts, successful_recalls, suspended,
+                deleted_at, created_at, modified_at, guid, changed_at, reviewed_at, ancestors,
+                guid_hint
+            FROM {from}.cards AS fc
+            WHERE NOT EXISTS (SELECT 1 FROM {into}.cards WHERE guid = fc.guid)
+                AND NOT EXISTS (
+                    SELECT 1 FROM {into}.deleted_cards AS d
+                    WHERE d.guid = fc.guid AND d.deleted_at >= max(fc.changed_at, fc.reviewed_at)
+                )
+            "#
+        ),
+        [],
+    )?;
+
+    changes.cards_updated = conn.query_row(
+        &format!(
+            r#"
+            SELECT COUNT(*) FROM {into}.cards AS ic
+            JOIN {from}.cards AS fc ON fc.guid = ic.guid
+            WHERE (fc.changed_at > ic.changed_at AND fc.guid NOT IN ({HELD_BACK_CARDS}))
+                OR fc.reviewed_at > ic.reviewed_at
+            "#
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+
+    // The replaced content stays in the card's history.
+    //
+    // Notes aren't synced either, so a note whose card gets other content
+    // would render over it with its next edit. Such a note is dropped and its
+    // cards become plain cards.
+    conn.execute_batch(&format!(
+        r#"
+        INSERT INTO {into}.card_revisions (card_id, content)
+        SELECT ic.card_id, ic.content FROM {into}.cards AS ic
+        JOIN {from}.cards AS fc ON fc.guid = ic.guid
+        WHERE fc.changed_at > ic.changed_at AND fc.content != ic.content
+            AND fc.guid NOT IN ({HELD_BACK_CARDS});
+
+        CREATE TEMP TABLE detached_notes AS
+        SELECT DISTINCT ic.note_id FROM {into}.cards AS ic
+        JOIN {from}.cards AS fc ON fc.guid = ic.guid
+        WHERE fc.changed_at > ic.changed_at AND fc.content != ic.content
+            AND fc.guid NOT IN ({HELD_BACK_CARDS}) AND ic.note_id IS NOT NULL;
+
+        UPDATE {into}.cards
+        SET (
+            content, suspended, deleted_at, created_at, modified_at, changed_at, ancestors,
+            note_id, template_id
+        ) = (
+            SELECT content, suspended, deleted_at, created_at, modified_at, changed_at, ancestors,
+                iif(content = cards.content, cards.note_id, NULL),
+                iif(content = cards.content, cards.template_id, NULL)
+            FROM {from}.cards AS fc WHERE fc.guid = cards.guid
+        )
+        WHERE EXISTS (
+            SELECT 1 FROM {from}.cards AS fc
+            WHERE fc.guid = cards.guid AND fc.changed_at > cards.changed_at
+                AND fc.guid NOT IN ({HELD_BACK_CARDS})
+        );
+
+        -- The other cards of those notes.
+        UPDATE {into}.cards SET note_id = NULL, template_id = NULL
+        WHERE note_id IN (SELECT note_id FROM temp.detached_notes);
+
+        DROP TABLE temp.detached_notes;
+
+        UPDATE {into}.cards
+        SET (due_date, due_days, recall_attempts, successful_recalls, reviewed_at) = (
+            SELECT due_date, due_days, recall_attempts, successful_recalls, reviewed_at
+            FROM {from}.cards AS fc WHERE fc.guid = cards.guid
+        )
+        WHERE EXISTS (
+            SELECT 1 FROM {from}.cards AS fc
+            WHERE fc.guid = cards.guid AND fc.reviewed_at > cards.reviewed_at
+        );
+        "#
+    ))?;
+
+    let deleted_cards = format!(
+        r#"
+        SELECT card_id FROM {into}.cards AS ic
+        JOIN {into}.deleted_cards AS d ON d.guid = ic.guid
+        WHERE d.deleted_at > max(ic.changed_at, ic.reviewed_at)
+        "#
+    );
+    for table in ["card_tag", "card_revisions", "card_conflicts"] {
+        conn.execute(
+            &format!("DELETE FROM {into}.{table} WHERE card_id IN ({deleted_cards})"),
+            [],
+        )?;
+    }
+    changes.cards_deleted = conn.execute(
+        &format!("DELETE FROM {into}.cards WHERE card_id IN ({deleted_cards})"),
+        [],
+    )?;
+    conn.execute_batch(&format!(
+        r#"
+        DELETE FROM {into}.note_values WHERE note_id NOT IN (
+            SELECT note_id FROM {into}.cards WHERE note_id IS NOT NULL
+        );
+        DELETE FROM {into}.notes WHERE note_id NOT IN (
+            SELECT note_id FROM {into}.cards WHERE note_id IS NOT NULL
+        );
+        "#
+    ))?;
+
+    let deleted_tags = format!(
+        r#"
+        SELECT tag_id FROM {into}.tags AS it
+        JOIN {into}.deleted_tags AS d ON d.name = it.name
+        WHERE d.deleted_at > it.changed_at
+        "#
+    );
+    conn.execute(
+        &format!("DELETE FROM {into}.card_tag WHERE tag_id IN ({deleted_tags})"),
+        [],
+    )?;
+    changes.tags_deleted = conn.execute(
+        &format!("DELETE FROM {into}.tags WHERE tag_id IN ({deleted_tags})"),
+        [],
+    )?;
+
+    // Copies that each made a tag of the same name settle on the smaller id.
+    // A renamed tag keeps its id until its old name is gone from both copies.
+    conn.execute_batch(&format!(
+        r#"
+        UPDATE {into}.tags SET guid = (SELECT guid FROM {from}.tags AS ft WHERE ft.name = tags.name)
+        WHERE EXISTS (
+            SELECT 1 FROM {from}.tags AS ft
+            WHERE ft.name = tags.name AND ft.guid < tags.guid
+                AND NOT EXISTS (SELECT 1 FROM {into}.tags AS it WHERE it.guid = ft.guid)
+        );
+        "#
+    ))?;
+
+    changes.card_tags_added = conn.execute(
+        &format!(
+            r#"
+            INSERT INTO {into}.card_tag (card_id, tag_id, changed_at)
+            SELECT ic.card_id, it.tag_id, fct.changed_at FROM {from}.card_tag AS fct
+            JOIN {from}.cards AS fc ON fc.card_id = fct.card_id
+            JOIN {from}.tags AS ft ON ft.tag_id = fct.tag_id
+            JOIN {into}.cards AS ic ON ic.guid = fc.guid
+            JOIN {into}.tags AS it ON it.name = ft.name
+            WHERE NOT EXISTS (
+                    SELECT 1 FROM {into}.card_tag
+                    WHERE card_id = ic.card_id AND tag_id = it.tag_id
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM {into}.deleted_card_tags AS d
+                    WHERE d.card_guid = fc.guid AND d.tag_name = ft.name
+                        AND d.deleted_at >= fct.changed_at
+                )
+            "#
+        ),
+        [],
+    )?;
+
+    changes.card_tags_deleted = conn.execute(
+        &format!(
+            r#"
+            DELETE FROM {into}.card_tag WHERE EXISTS (
+                SELECT 1 FROM {into}.cards AS ic, {into}.tags AS it, {into}.deleted_card_tags AS d
+                WHERE ic.card_id = card_tag.card_id AND it.tag_id = card_tag.tag_id
+                    AND d.card_guid = ic.guid AND d.tag_name = it.name
+                    AND d.deleted_at > card_tag.changed_at
+            )
+            "#
+        ),
+        [],
+    )?;
+
+    // Tombstones of rows that are newer than them are no longer needed.
+    conn.execute_batch(&format!(
+        r#"
+        DELETE FROM {into}.deleted_cards WHERE guid IN (SELECT guid FROM {into}.cards);
+
+        DELETE FROM {into}.deleted_tags WHERE name IN (SELECT name FROM {into}.tags);
+
+        DELETE FROM {into}.deleted_card_tags WHERE EXISTS (
+            SELECT 1 FROM {into}.card_tag AS ict
+            JOIN {into}.cards AS ic ON ic.card_id = ict.card_id
+            JOIN {into}.tags AS it ON it.tag_id = ict.tag_id
+            WHERE ic.guid = deleted_card_tags.card_guid AND it.name = deleted_card_tags.tag_name
+        );
+        "#
+    ))?;
+
+    Ok(changes)
+}
@@ -0,0 +1,141 @@
+use super::database::{Id, Tag};
+
+pub const SEPARATOR: &str = "::";
+
+#[derive(Debug)]
+pub struct TagNode {
+    pub id: Id,
+    pub name: String,
+    pub path: String,
+    pub children: Vec<TagNode>,
+}
+
+pub fn parent(path: &str) -> Option<&str> {
+    path.rfind(SEPARATOR).map(|i| &path[..i])
+}
+
+pub fn ancestors(path: &str) -> Vec<&str> {
+    let mut ancestors = Vec::new();
+    let mut current = path;
+    while let Some(parent) = parent(current) {
+        ancestors.push(parent);
+        current = parent;
+    }
+    ancestors.reverse();
+    ancestors
+}
+
+pub fn is_descendant(path: &str, ancestor: &str) -> bool {
+    path.len() > ancestor.len() + SEPARATOR.len()
+        && path.starts_with(ancestor)
+        && path[ancestor.len()..].starts_with(SEPARATOR)
+}
+
+pub fn depth(path: &str) -> usize {
+    path.matches(SEPARATOR).count()
+}
+
+pub fn leaf_name(path: &str) -> &str {
+    match path.rfind(SEPARATOR) {
+        Some(i) => &path[i + SEPARATOR.len()..],
+        None => path,
+    }
+}
+
+// Tags whose parent is missing are placed at the root under their full path.
+pub fn build(tags: &[Tag]) -> Vec<TagNode> {
+    let mut sorted = tags.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut roots = Vec::new();
+    for tag in sorted {
+        let node = TagNode {
+            id: tag.id,
+            name: leaf_name(&tag.name).to_owned(),
+            path: tag.name.clone(),
+            children: Vec::new(),
+        };
+
+        match parent(&tag.name).and_then(|parent| find_mut(&mut roots, parent)) {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(TagNode {
+                name: tag.name.clone(),
+                ..node
+            }),
+        }
+    }
+
+    roots
+}
+
+fn find_mut<'a>(nodes: &'a mut [TagNode], path: &str) -> Option<&'a mut TagNode> {
+    for node in nodes {
+        if node.path == path {
+            return Some(node);
+        }
+        if is_descendant(path, &node.path) {
+            return find_mut(&mut node.children, path);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(names: &[&str]) -> Vec<Tag> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Tag {
+                id: i + 1,
+                guid: String::new(),
+                name: name.to_string(),
+            })
+            .collect()
+    }
+
+    // Paths of the nodes, each followed by the paths of its children.
+    fn paths(nodes: &[TagNode]) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|node| match node.children.is_empty() {
+                true => node.path.clone(),
+                false => format!("{} [{}]", node.path, paths(&node.children).join(", ")),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_paths() {
+        assert_eq!(ancestors("a::b::c"), ["a", "a::b"]);
+        assert!(ancestors("a").is_empty());
+        assert_eq!(ancestors("a::"), ["a"]);
+        assert_eq!(depth("a"), 0);
+        assert_eq!(depth("a::b::c"), 2);
+        assert_eq!(leaf_name("a::b::c"), "c");
+        assert_eq!(leaf_name("a"), "a");
+        assert_eq!(leaf_name("a::"), "");
+    }
+
+    #[test]
+    fn only_counts_whole_levels_as_descendants() {
+        assert!(is_descendant("a::b", "a"));
+        assert!(is_descendant("a::b::c", "a"));
+        assert!(!is_descendant("a::bc", "a::b"));
+        assert!(!is_descendant("ab", "a"));
+        assert!(!is_descendant("a", "a"));
+        assert!(!is_descendant("a::", "a"));
+        assert!(!is_descendant("a", "a::b"));
+    }
+
+    #[test]
+    fn builds_the_tree_in_order() {
+        let tree = build(&tags(&["a::bc", "b", "a::b::c", "a", "a::b", "x::y"]));
+        assert_eq!(paths(&tree), ["a [a::b [a::b::c], a::bc]", "b", "x::y"]);
+        assert_eq!(tree[0].children[1].name, "bc");
+        // Without its parent it keeps its full name.
+        assert_eq!(tree[2].name, "x::y");
+    }
+}
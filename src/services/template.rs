@@ -0,0 +1,87 @@
+// Renders note fields into card content.
+//
+// `{{Field}}` is replaced by the field value, `{{#Field}}...{{/Field}}` is only
+// kept when the field is not empty and `{{^Field}}...{{/Field}}` only when it is.
+pub fn render(template: &str, fields: &[(&str, &str)]) -> String {
+    let value = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map_or("", |(_, value)| value)
+    };
+
+    let mut rendered = String::new();
+    let mut sections: Vec<(&str, bool)> = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let len = match rest[start..].find("}}") {
+            Some(len) => len,
+            None => break,
+        };
+
+        let is_visible = sections.iter().all(|(_, visible)| *visible);
+        if is_visible {
+            rendered.push_str(&rest[..start]);
+        }
+
+        let tag = rest[start + 2..start + len].trim();
+        rest = &rest[start + len + 2..];
+
+        if let Some(name) = tag.strip_prefix('#') {
+            let name = name.trim();
+            sections.push((name, !value(name).trim().is_empty()));
+        } else if let Some(name) = tag.strip_prefix('^') {
+            let name = name.trim();
+            sections.push((name, value(name).trim().is_empty()));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            if let Some(pos) = sections.iter().rposition(|(section, _)| *section == name) {
+                sections.truncate(pos);
+            }
+        } else if is_visible {
+            rendered.push_str(value(tag));
+        }
+    }
+
+    if sections.iter().all(|(_, visible)| *visible) {
+        rendered.push_str(rest);
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    const FIELDS: [(&str, &str); 3] = [("Front", "Hund"), ("Back", "dog"), ("Extra", " ")];
+
+    #[test]
+    fn replaces_fields() {
+        assert_eq!(render("{{Front}} - {{ Back }}", &FIELDS), "Hund - dog");
+        assert_eq!(render("{{Missing}}!", &FIELDS), "!");
+    }
+
+    #[test]
+    fn keeps_sections_by_field_value() {
+        assert_eq!(render("{{#Back}}({{Back}}){{/Back}}", &FIELDS), "(dog)");
+        assert_eq!(render("a{{#Extra}}b{{/Extra}}c", &FIELDS), "ac");
+        assert_eq!(render("a{{^Extra}}b{{/Extra}}c", &FIELDS), "abc");
+        assert_eq!(render("a{{^Front}}b{{/Front}}c", &FIELDS), "ac");
+    }
+
+    #[test]
+    fn nests_sections() {
+        let template = "{{#Front}}1{{#Extra}}2{{/Extra}}3{{/Front}}4";
+        assert_eq!(render(template, &FIELDS), "134");
+        let template = "{{#Extra}}1{{#Front}}2{{/Front}}3{{/Extra}}4";
+        assert_eq!(render(template, &FIELDS), "4");
+    }
+
+    #[test]
+    fn keeps_unclosed_tags_as_text() {
+        assert_eq!(render("{{Front}} {{Back", &FIELDS), "Hund {{Back");
+        assert_eq!(render("{{#Extra}}hidden", &FIELDS), "");
+    }
+}